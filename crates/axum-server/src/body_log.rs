@@ -0,0 +1,327 @@
+// request and response bodies logged at DEBUG, as the body_log settings in
+// config.toml allow. only the first body_log_max_bytes of a body are held
+// back, the rest streams through unread
+use axum::{
+    body::{Body, Bytes},
+    extract::Request,
+    http::{header, HeaderMap, HeaderName, StatusCode, Uri},
+    middleware::Next,
+    response::Response,
+};
+use futures_util::{stream, StreamExt};
+use serde_json::Value;
+use tracing::Span;
+
+use crate::{config, server_config::ServerConfig};
+
+const REDACTED: &str = "[redacted]";
+
+pub async fn log_bodies(req: Request, next: Next) -> Result<Response, (StatusCode, String)> {
+    let config = config();
+    let path = req.uri().path();
+    let skipped = !config.body_log
+        || config
+            .body_log_skip_paths
+            .iter()
+            .any(|skip| path.starts_with(skip.as_str()))
+        || rand::random::<f64>() >= config.body_log_sample_rate;
+    if skipped {
+        return Ok(next.run(req).await);
+    }
+
+    let (parts, body) = req.into_parts();
+    log_cookies("request", &parts.headers, header::COOKIE, config);
+    let body = log_body("request", &parts.headers, body, config).await?;
+    let req = Request::from_parts(parts, body);
+
+    let res = next.run(req).await;
+
+    let (parts, body) = res.into_parts();
+    log_cookies("response", &parts.headers, header::SET_COOKIE, config);
+    let body = log_body("response", &parts.headers, body, config).await?;
+    Ok(Response::from_parts(parts, body))
+}
+
+// the TraceLayer span of a request, with the body_log_redact fields of the
+// query string hidden as they are in form bodies
+pub fn make_span(req: &Request) -> Span {
+    tracing::debug_span!(
+        "request",
+        method = %req.method(),
+        uri = %redact_uri(req.uri(), &config().body_log_redact),
+        version = ?req.version(),
+    )
+}
+
+fn redact_uri(uri: &Uri, fields: &[String]) -> String {
+    match uri.query() {
+        Some(query) => format!("{}?{}", uri.path(), redact_form(query, fields)),
+        None => uri.path().to_owned(),
+    }
+}
+
+// "application/json" for "application/json; charset=utf-8"
+fn media_type(headers: &HeaderMap) -> Option<String> {
+    let value = headers.get(header::CONTENT_TYPE)?.to_str().ok()?;
+    let media_type = value.split(';').next()?.trim().to_ascii_lowercase();
+    Some(media_type)
+}
+
+async fn log_body(
+    direction: &str,
+    headers: &HeaderMap,
+    body: Body,
+    config: &ServerConfig,
+) -> Result<Body, (StatusCode, String)> {
+    let (body, shown) = read_body(direction, headers, body, config).await?;
+    if let Some(shown) = shown {
+        tracing::debug!("{direction} body: {shown}");
+    }
+    Ok(body)
+}
+
+// the start of `body` as it is logged, if its type is listed, and a body with
+// every byte of the original
+async fn read_body(
+    direction: &str,
+    headers: &HeaderMap,
+    body: Body,
+    config: &ServerConfig,
+) -> Result<(Body, Option<String>), (StatusCode, String)> {
+    let Some(media_type) = media_type(headers).filter(|media_type| {
+        config
+            .body_log_content_types
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(media_type))
+    }) else {
+        return Ok((body, None));
+    };
+
+    // read one chunk past the limit to know whether there is more
+    let max = config.body_log_max_bytes;
+    let mut data = body.into_data_stream();
+    let mut head = Vec::new();
+    let mut ended = false;
+    while head.len() <= max {
+        match data.next().await {
+            Some(Ok(chunk)) => head.extend_from_slice(&chunk),
+            Some(Err(err)) => {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    format!("failed to read {direction} body: {err}"),
+                ));
+            }
+            None => {
+                ended = true;
+                break;
+            }
+        }
+    }
+
+    let head = Bytes::from(head);
+    let fields = &config.body_log_redact;
+    let shown = redact(&media_type, &head[..head.len().min(max)], ended, fields);
+    if ended {
+        let count = head.len();
+        Ok((Body::from(head), Some(format!("{count} bytes = {shown:?}"))))
+    } else {
+        let head = stream::once(async { Ok::<_, axum::Error>(head) });
+        Ok((
+            Body::from_stream(head.chain(data)),
+            Some(format!("first {max} bytes = {shown:?}")),
+        ))
+    }
+}
+
+fn is_redacted(key: &str, fields: &[String]) -> bool {
+    fields.iter().any(|field| field == key)
+}
+
+// the body with the values of `fields` replaced. json that was cut short
+// cannot be searched for them and is left out
+fn redact(media_type: &str, body: &[u8], complete: bool, fields: &[String]) -> String {
+    let text = String::from_utf8_lossy(body);
+    if fields.is_empty() {
+        return text.into_owned();
+    }
+    match media_type {
+        "application/x-www-form-urlencoded" => redact_form(&text, fields),
+        "application/json" => match serde_json::from_slice::<Value>(body) {
+            Ok(mut json) => {
+                redact_json(&mut json, fields);
+                json.to_string()
+            }
+            Err(_) if !complete => "(cut short, not shown)".to_owned(),
+            Err(_) => "(not json, not shown)".to_owned(),
+        },
+        _ => text.into_owned(),
+    }
+}
+
+// keys are compared decoded, na%6De is name too
+fn redact_form(text: &str, fields: &[String]) -> String {
+    text.split('&')
+        .map(|pair| {
+            let key = pair.split_once('=').map_or(pair, |(key, _)| key);
+            let decoded: String = form_urlencoded::parse(key.as_bytes())
+                .map(|(key, _)| key)
+                .collect();
+            if is_redacted(&decoded, fields) {
+                format!("{key}={REDACTED}")
+            } else {
+                pair.to_owned()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+fn redact_json(json: &mut Value, fields: &[String]) {
+    match json {
+        Value::Object(object) => {
+            for (key, value) in object {
+                if is_redacted(key, fields) {
+                    *value = Value::String(REDACTED.to_owned());
+                } else {
+                    redact_json(value, fields);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|item| redact_json(item, fields)),
+        _ => {}
+    }
+}
+
+fn log_cookies(direction: &str, headers: &HeaderMap, name: HeaderName, config: &ServerConfig) {
+    for value in headers.get_all(&name) {
+        let Ok(value) = value.to_str() else {
+            continue;
+        };
+        let shown = redact_cookies(value, &config.body_log_redact_cookies);
+        tracing::debug!("{direction} {name}: {shown}");
+    }
+}
+
+// a Cookie or Set-Cookie value with the values of `cookies` hidden
+fn redact_cookies(value: &str, cookies: &[String]) -> String {
+    value
+        .split(';')
+        .map(|pair| match pair.trim().split_once('=') {
+            Some((key, _)) if cookies.iter().any(|cookie| cookie == key) => {
+                format!("{key}={REDACTED}")
+            }
+            _ => pair.trim().to_owned(),
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn form_fields_are_redacted() {
+        let fields = names(&["name"]);
+        let form = "application/x-www-form-urlencoded";
+        assert_eq!(
+            redact(form, b"name=alice&move=1,2", true, &fields),
+            "name=[redacted]&move=1,2"
+        );
+        assert_eq!(
+            redact(form, b"na%6De=alice&n%61me=bob", true, &fields),
+            "na%6De=[redacted]&n%61me=[redacted]"
+        );
+        assert_eq!(redact(form, b"name", true, &fields), "name=[redacted]");
+        assert_eq!(
+            redact(form, b"nickname=alice", true, &fields),
+            "nickname=alice"
+        );
+        assert_eq!(redact(form, b"name=alice", true, &[]), "name=alice");
+    }
+
+    #[test]
+    fn json_keys_are_redacted_at_any_depth() {
+        let fields = names(&["name"]);
+        let json = "application/json";
+        assert_eq!(
+            redact(
+                json,
+                br#"{"name":"alice","players":[{"name":"bob","wins":2}]}"#,
+                true,
+                &fields
+            ),
+            r#"{"name":"[redacted]","players":[{"name":"[redacted]","wins":2}]}"#
+        );
+        assert_eq!(
+            redact(json, br#"{"name":"ali"#, false, &fields),
+            "(cut short, not shown)"
+        );
+        assert_eq!(
+            redact(json, b"name=alice", true, &fields),
+            "(not json, not shown)"
+        );
+    }
+
+    #[test]
+    fn query_strings_are_redacted() {
+        let fields = names(&["name"]);
+        let uri: Uri = "/ttt.php?name=alice&board=x".parse().unwrap();
+        assert_eq!(
+            redact_uri(&uri, &fields),
+            "/ttt.php?name=[redacted]&board=x"
+        );
+        let uri: Uri = "/ttt.php".parse().unwrap();
+        assert_eq!(redact_uri(&uri, &fields), "/ttt.php");
+    }
+
+    #[test]
+    fn listed_cookies_are_redacted() {
+        let cookies = names(&["id"]);
+        assert_eq!(
+            redact_cookies("id=abc; theme=dark", &cookies),
+            "id=[redacted]; theme=dark"
+        );
+        assert_eq!(
+            redact_cookies("id=abc; Path=/; HttpOnly", &cookies),
+            "id=[redacted]; Path=/; HttpOnly"
+        );
+        assert_eq!(redact_cookies("sid=abc", &cookies), "sid=abc");
+    }
+
+    #[tokio::test]
+    async fn only_the_first_bytes_are_shown() {
+        let config = ServerConfig {
+            body_log_max_bytes: 4,
+            ..ServerConfig::default()
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::CONTENT_TYPE,
+            "application/x-www-form-urlencoded".parse().unwrap(),
+        );
+        let (body, shown) = read_body("request", &headers, Body::from("move=1,2"), &config)
+            .await
+            .unwrap();
+        assert_eq!(shown.unwrap(), "first 4 bytes = \"move\"");
+        let bytes = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        assert_eq!(bytes, "move=1,2");
+
+        let (body, shown) = read_body("request", &headers, Body::from("mov"), &config)
+            .await
+            .unwrap();
+        assert_eq!(shown.unwrap(), "3 bytes = \"mov\"");
+        let bytes = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        assert_eq!(bytes, "mov");
+
+        headers.insert(header::CONTENT_TYPE, "image/png".parse().unwrap());
+        let (_, shown) = read_body("request", &headers, Body::from("move=1,2"), &config)
+            .await
+            .unwrap();
+        assert_eq!(shown, None);
+    }
+}
@@ -1,52 +1,81 @@
 use std::net::SocketAddr;
 
-use config::Config;
-use once_cell::sync::Lazy;
-
-use axum::{
-    body::{Body, Bytes},
-    http::StatusCode,
-    response::Response,
-};
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+use once_cell::sync::{Lazy, OnceCell};
+
+use axum::{body::Body, response::Response};
 use axum::{extract::Request, middleware::Next};
 use chrono::Local;
 
-use http_body_util::BodyExt;
-
 use tracing_subscriber::fmt;
 use tracing_subscriber::layer::SubscriberExt;
 
+use axum_serve::Handle;
+use tokio::signal::unix::{signal, SignalKind};
 use tower_http::services::ServeDir;
 use tower_http::trace::TraceLayer;
-use tower_sessions::{cookie::time::Duration, Expiry, MemoryStore, SessionManagerLayer};
+use tower_sessions::{cookie::time::Duration, Expiry, SessionManagerLayer};
 
+mod body_log;
 pub mod routers;
 use routers::*;
+mod metrics;
+mod server_config;
+mod session_store;
+mod stats;
+mod tls;
+
+use server_config::ServerConfig;
+
+/// Serves the games
+#[derive(Debug, Parser)]
+struct Cli {
+    /// Settings file, config.toml if left out. APP_ environment variables
+    /// override it
+    #[arg(long)]
+    config: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
 
-#[derive(Debug)]
-struct ServerConfig {
-    ip: [u8; 4],
-    http_port: u16,
-    submission_id: String,
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Prints the settings the server would run with, or what is wrong with them
+    CheckConfig,
 }
 
-static CONFIG: Lazy<ServerConfig> = Lazy::new(|| {
-    let config = Config::builder()
-        .add_source(config::File::with_name("config.toml"))
-        .build()
-        .unwrap();
+static CONFIG: OnceCell<ServerConfig> = OnceCell::new();
 
-    dbg!(ServerConfig {
-        ip: config.get("ip").unwrap(),
-        http_port: config.get("http_port").unwrap(),
-        submission_id: config.get("submission_id").unwrap(),
-    })
-});
+// the settings, loaded before anything else runs
+fn config() -> &'static ServerConfig {
+    CONFIG.get().expect("the configuration is loaded first")
+}
 
 #[tokio::main]
 async fn main() {
+    let cli = Cli::parse();
+    // the default file may be missing, one named on the command line may not
+    let loaded = ServerConfig::load(cli.config.as_deref());
+    if let Some(Command::CheckConfig) = cli.command {
+        match loaded {
+            Ok(config) => print!("{}", config.to_toml()),
+            Err(err) => {
+                eprintln!("{err}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    let loaded = loaded.unwrap_or_else(|err| {
+        eprintln!("{err}");
+        std::process::exit(1);
+    });
+    CONFIG.set(loaded).unwrap();
+
     let file_appender = tracing_appender::rolling::never("./logs", Local::now().to_rfc3339());
-    let (file_writer, _guard) = tracing_appender::non_blocking(file_appender);
+    let (file_writer, log_guard) = tracing_appender::non_blocking(file_appender);
     tracing::subscriber::set_global_default(
         fmt::Subscriber::builder()
             .with_max_level(tracing::Level::DEBUG)
@@ -55,75 +84,114 @@ async fn main() {
             .with(fmt::Layer::default().with_writer(std::io::stdout)),
     )
     .expect("Unable to set global tracing subscriber");
-
-    let session_store = MemoryStore::default();
-    let session_layer = SessionManagerLayer::new(session_store)
-        .with_secure(false)
+    tracing::info!("configuration:\n{}", config().to_toml());
+
+    // a database that cannot be opened stops the server before it listens
+    Lazy::force(&stats::DB);
+
+    let session_store =
+        session_store::Store::open(&config().session_store, config().session_path.as_deref())
+            .expect("Unable to open the session store");
+    session_store.spawn_cleanup(std::time::Duration::from_secs(
+        config().session_cleanup_secs,
+    ));
+    let session_layer = SessionManagerLayer::new(session_store.clone())
+        .with_secure(config().tls_enabled())
         .with_expiry(Expiry::OnInactivity(Duration::seconds(3600)));
 
-    let app = axum::Router::new()
+    let app = routers::routes()
+        .into_iter()
+        .fold(axum::Router::new(), |app, (path, router)| {
+            app.nest(path, router)
+        })
         .nest_service("/", ServeDir::new("static"))
-        .nest("/ttt.php", ttt_router::new_ttt_router())
-        .nest("/connect.php", connect_router::new_connect_router())
-        .nest(
-            "/battleship.php",
-            battleship_router::new_battleship_router(),
-        )
         .layer(axum::middleware::from_fn(append_headers))
-        .layer(axum::middleware::from_fn(print_request_response))
-        .layer(TraceLayer::new_for_http())
-        .layer(session_layer);
+        .layer(session_layer)
+        // outside the session layer to see the session cookie being set
+        .layer(axum::middleware::from_fn(body_log::log_bodies))
+        .layer(TraceLayer::new_for_http().make_span_with(body_log::make_span))
+        .layer(axum::middleware::from_fn(metrics::track_requests));
+
+    let handle = Handle::new();
+    tokio::spawn(shutdown_on_signal(handle.clone()));
+    serve(app, handle).await;
+
+    // requests have finished, so have their session writes
+    tracing::info!("stopped serving");
+    session_store.close().await;
+    tracing::info!("shut down");
+    // flushes the log file
+    drop(log_guard);
+}
 
-    let addr = SocketAddr::from((CONFIG.ip, CONFIG.http_port));
-    let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
+// https on https_port with http_port redirecting there when a certificate is
+// configured, plain http on http_port otherwise. returns once `handle` has
+// shut the servers down
+async fn serve(app: axum::Router, handle: Handle) {
+    let addr = SocketAddr::from((config().ip, config().http_port));
+    let (Some(cert), Some(key)) = (&config().tls_cert, &config().tls_key) else {
+        tracing::debug!("Server listening on {}", addr);
+        axum_serve::bind(addr)
+            .handle(handle)
+            .serve(app.into_make_service())
+            .await
+            .unwrap();
+        return;
+    };
+
+    let rustls_config = tls::load(cert, key).await.unwrap_or_else(|err| {
+        eprintln!("{err}");
+        std::process::exit(1);
+    });
+    tokio::spawn(tls::reload_on_sighup(
+        rustls_config.clone(),
+        cert.clone(),
+        key.clone(),
+    ));
+    tracing::debug!("Redirecting {} to https", addr);
+    tokio::spawn(
+        axum_serve::bind(addr)
+            .handle(handle.clone())
+            .serve(tls::new_redirect_router().into_make_service()),
+    );
+
+    let tls_addr = SocketAddr::from((config().ip, config().https_port));
+    tracing::debug!("Server listening on {}", tls_addr);
+    axum_serve::bind_rustls(tls_addr, rustls_config)
+        .handle(handle)
+        .serve(app.into_make_service())
+        .await
+        .unwrap();
+}
 
-    tracing::debug!("Server listening on {}", addr);
-    axum::serve(listener, app).await.unwrap();
+// on SIGTERM or SIGINT fails /readyz for shutdown_delay_secs while still
+// serving, then stops accepting connections and gives the open ones
+// shutdown_grace_secs to finish before they are dropped
+async fn shutdown_on_signal(handle: Handle) {
+    let mut terminate = signal(SignalKind::terminate()).expect("Unable to listen for SIGTERM");
+    tokio::select! {
+        _ = terminate.recv() => tracing::info!("received SIGTERM"),
+        _ = tokio::signal::ctrl_c() => tracing::info!("received SIGINT"),
+    }
+    health_router::SHUTTING_DOWN.store(true, std::sync::atomic::Ordering::Relaxed);
+    let delay = std::time::Duration::from_secs(config().shutdown_delay_secs);
+    tracing::info!(
+        "not ready, still accepting connections for {}s",
+        delay.as_secs()
+    );
+    tokio::time::sleep(delay).await;
+    let grace = std::time::Duration::from_secs(config().shutdown_grace_secs);
+    tracing::info!(
+        "shutting down, waiting up to {}s for {} open connections",
+        grace.as_secs(),
+        handle.connection_count()
+    );
+    handle.graceful_shutdown(Some(grace));
 }
 
 async fn append_headers(request: Request, next: Next) -> Response<Body> {
     let mut response = next.run(request).await;
     let headers = response.headers_mut();
-    headers.insert("x-cse356", CONFIG.submission_id.parse().unwrap());
+    headers.insert("x-cse356", config().submission_id.parse().unwrap());
     response
 }
-
-async fn print_request_response(
-    req: Request,
-    next: Next,
-) -> Result<Response<Body>, (StatusCode, String)> {
-    let (parts, body) = req.into_parts();
-    let bytes = buffer_and_print("request", body).await?;
-    let req = Request::from_parts(parts, Body::from(bytes));
-
-    let res = next.run(req).await;
-
-    let (parts, body) = res.into_parts();
-    let bytes = buffer_and_print("response", body).await?;
-    let res = Response::from_parts(parts, Body::from(bytes));
-
-    Ok(res)
-}
-
-async fn buffer_and_print<B>(direction: &str, body: B) -> Result<Bytes, (StatusCode, String)>
-where
-    B: axum::body::HttpBody<Data = Bytes>,
-    B::Error: std::fmt::Display,
-{
-    let bytes = match body.collect().await {
-        Ok(collected) => collected.to_bytes(),
-        Err(err) => {
-            return Err((
-                StatusCode::BAD_REQUEST,
-                format!("failed to read {direction} body: {err}"),
-            ));
-        }
-    };
-
-    if let Ok(body) = std::str::from_utf8(&bytes) {
-        let count = body.len();
-        tracing::debug!("{direction} body: {count} bytes = {body:?}");
-    }
-
-    Ok(bytes)
-}
@@ -0,0 +1,261 @@
+// counters behind /metrics, written out in the prometheus text format
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use axum::{
+    body::Body, extract::MatchedPath, extract::Request, middleware::Next, response::Response,
+};
+use once_cell::sync::Lazy;
+use tower_sessions::cookie::time::OffsetDateTime;
+use tower_sessions::session::Id;
+
+use ui_components::stats::PlayerResult;
+
+// upper bounds of the latency buckets, in seconds
+const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+// who the game is played against, "server" for the engine and "room" for
+// another player
+pub const SERVER: &str = "server";
+pub const ROOM: &str = "room";
+
+#[derive(Debug, Default)]
+struct Histogram {
+    // requests no slower than each of BUCKETS, not yet cumulative
+    buckets: [u64; BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        if let Some(bucket) = BUCKETS.iter().position(|bound| seconds <= *bound) {
+            self.buckets[bucket] += 1;
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+}
+
+#[derive(Debug, Default)]
+struct Metrics {
+    // (method, route, status)
+    requests: BTreeMap<(String, String, u16), u64>,
+    // (method, route)
+    latencies: BTreeMap<(String, String), Histogram>,
+    // (game, opponent)
+    started: BTreeMap<(String, &'static str), u64>,
+    finished: BTreeMap<(String, &'static str), u64>,
+    // (game, result) of games against the server, as the server sees them
+    server_results: BTreeMap<(String, &'static str), u64>,
+    // when each session seen since the start expires
+    sessions: HashMap<Id, OffsetDateTime>,
+}
+
+static METRICS: Lazy<Mutex<Metrics>> = Lazy::new(Mutex::default);
+
+// counts and times every request by the route it matched, so `/room.php/:code`
+// is one route however many rooms there are
+pub async fn track_requests(req: Request, next: Next) -> Response<Body> {
+    let method = req.method().to_string();
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("other", MatchedPath::as_str)
+        .to_owned();
+    let start = Instant::now();
+    let response = next.run(req).await;
+    let seconds = start.elapsed().as_secs_f64();
+
+    let mut metrics = METRICS.lock().unwrap();
+    let status = response.status().as_u16();
+    *metrics
+        .requests
+        .entry((method.clone(), route.clone(), status))
+        .or_default() += 1;
+    metrics
+        .latencies
+        .entry((method, route))
+        .or_default()
+        .observe(seconds);
+    response
+}
+
+pub fn game_started(game: &str, opponent: &'static str) {
+    *METRICS
+        .lock()
+        .unwrap()
+        .started
+        .entry((game.to_owned(), opponent))
+        .or_default() += 1;
+}
+
+// `player_result` is the result of the player against the server, None for
+// games between two players
+pub fn game_finished(game: &str, player_result: Option<PlayerResult>) {
+    let mut metrics = METRICS.lock().unwrap();
+    let opponent = if player_result.is_some() {
+        SERVER
+    } else {
+        ROOM
+    };
+    *metrics
+        .finished
+        .entry((game.to_owned(), opponent))
+        .or_default() += 1;
+    if let Some(result) = player_result {
+        *metrics
+            .server_results
+            .entry((game.to_owned(), result.opposite().name()))
+            .or_default() += 1;
+    }
+}
+
+pub fn session_active(id: Id, expiry_date: OffsetDateTime) {
+    METRICS.lock().unwrap().sessions.insert(id, expiry_date);
+}
+
+pub fn session_ended(id: &Id) {
+    METRICS.lock().unwrap().sessions.remove(id);
+}
+
+// the exposition text, https://prometheus.io/docs/instrumenting/exposition_formats/
+pub fn render() -> String {
+    let mut metrics = METRICS.lock().unwrap();
+    let now = OffsetDateTime::now_utc();
+    metrics.sessions.retain(|_, expiry_date| *expiry_date > now);
+
+    let mut out = String::new();
+    header(
+        &mut out,
+        "http_requests_total",
+        "counter",
+        "Requests by method, route and status",
+    );
+    for ((method, route, status), count) in &metrics.requests {
+        let _ = writeln!(
+            out,
+            "http_requests_total{{method=\"{method}\",route=\"{}\",status=\"{status}\"}} {count}",
+            escape(route)
+        );
+    }
+
+    header(
+        &mut out,
+        "http_request_duration_seconds",
+        "histogram",
+        "Time to respond by method and route",
+    );
+    for ((method, route), histogram) in &metrics.latencies {
+        let labels = format!("method=\"{method}\",route=\"{}\"", escape(route));
+        let mut cumulative = 0;
+        for (bound, count) in BUCKETS.iter().zip(histogram.buckets) {
+            cumulative += count;
+            let _ = writeln!(
+                out,
+                "http_request_duration_seconds_bucket{{{labels},le=\"{bound}\"}} {cumulative}"
+            );
+        }
+        let _ = writeln!(
+            out,
+            "http_request_duration_seconds_bucket{{{labels},le=\"+Inf\"}} {}",
+            histogram.count
+        );
+        let _ = writeln!(
+            out,
+            "http_request_duration_seconds_sum{{{labels}}} {}",
+            histogram.sum
+        );
+        let _ = writeln!(
+            out,
+            "http_request_duration_seconds_count{{{labels}}} {}",
+            histogram.count
+        );
+    }
+
+    header(
+        &mut out,
+        "active_sessions",
+        "gauge",
+        "Unexpired sessions used since the server started",
+    );
+    let _ = writeln!(out, "active_sessions {}", metrics.sessions.len());
+
+    for (name, help, counts) in [
+        (
+            "games_started_total",
+            "Games started by game and opponent",
+            &metrics.started,
+        ),
+        (
+            "games_finished_total",
+            "Games finished by game and opponent",
+            &metrics.finished,
+        ),
+    ] {
+        header(&mut out, name, "counter", help);
+        for ((game, opponent), count) in counts {
+            let _ = writeln!(
+                out,
+                "{name}{{game=\"{game}\",opponent=\"{opponent}\"}} {count}"
+            );
+        }
+    }
+
+    header(
+        &mut out,
+        "server_games_total",
+        "counter",
+        "Results of the server's own games by game and result",
+    );
+    for ((game, result), count) in &metrics.server_results {
+        let _ = writeln!(
+            out,
+            "server_games_total{{game=\"{game}\",result=\"{result}\"}} {count}"
+        );
+    }
+
+    header(
+        &mut out,
+        "server_game_ratio",
+        "gauge",
+        "Share of the server's finished games with each result",
+    );
+    let mut totals: BTreeMap<&str, u64> = BTreeMap::new();
+    for ((game, _), count) in &metrics.server_results {
+        *totals.entry(game).or_default() += count;
+    }
+    for (game, total) in totals {
+        for result in [PlayerResult::Win, PlayerResult::Loss, PlayerResult::Draw] {
+            let count = metrics
+                .server_results
+                .get(&(game.to_owned(), result.name()))
+                .copied()
+                .unwrap_or_default();
+            let _ = writeln!(
+                out,
+                "server_game_ratio{{game=\"{game}\",result=\"{}\"}} {}",
+                result.name(),
+                count as f64 / total as f64
+            );
+        }
+    }
+    out
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+// label values are quoted, routes are the only ones not chosen here
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
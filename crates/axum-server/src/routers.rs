@@ -1,4 +1,32 @@
+pub mod api_router;
 pub mod battleship_router;
 pub mod connect_router;
+pub mod health_router;
 pub mod lib;
+pub mod openapi_router;
+pub mod record_router;
+pub mod room_router;
+pub mod stats_router;
 pub mod ttt_router;
+
+// every router and the path it is nested under, the static files aside. the
+// OpenAPI document is checked against this table
+pub fn routes() -> Vec<(&'static str, axum::Router)> {
+    vec![
+        ("/ttt.php", ttt_router::new_ttt_router()),
+        ("/connect.php", connect_router::new_connect_router()),
+        (
+            "/battleship.php",
+            battleship_router::new_battleship_router(),
+        ),
+        ("/record.php", record_router::new_record_router()),
+        ("/room.php", room_router::new_room_router()),
+        ("/ws", room_router::new_ws_router()),
+        ("/api/v1", api_router::new_api_router()),
+        ("/openapi.json", openapi_router::new_openapi_router()),
+        ("/leaderboard", stats_router::new_stats_router()),
+        ("/healthz", health_router::new_health_router()),
+        ("/readyz", health_router::new_ready_router()),
+        ("/metrics", health_router::new_metrics_router()),
+    ]
+}
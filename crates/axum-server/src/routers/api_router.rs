@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use axum::{
+    body::Bytes,
+    extract::Path,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json,
+};
+use once_cell::sync::Lazy;
+use rand::Rng;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use ui_components::api::{ApiGame, GameState, NewGame};
+use ui_components::stats::GameResult;
+
+use crate::{metrics, stats};
+
+const GAMES: [&str; 3] = ["ttt", "connect", "battleship"];
+// games nobody has played for this long are dropped
+const GAME_TTL: Duration = Duration::from_secs(3600);
+
+struct Entry {
+    game: ApiGame,
+    // the player's name, unnamed games are not recorded
+    name: Option<String>,
+    started: Instant,
+    touched: Instant,
+    // a move is being played, see move_handler
+    busy: bool,
+}
+
+static API_GAMES: Lazy<Mutex<HashMap<String, Entry>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+// frees the game with this id for the next move, also when the client hangs
+// up and the handler is dropped halfway
+struct BusyGuard(String);
+
+impl Drop for BusyGuard {
+    fn drop(&mut self) {
+        if let Some(entry) = API_GAMES.lock().unwrap().get_mut(&self.0) {
+            entry.busy = false;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct MoveBody {
+    r#move: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct GameResponse {
+    id: String,
+    #[serde(flatten)]
+    state: GameState,
+    // the server's reply to the move just played
+    server_move: Option<String>,
+}
+
+// every error is a json object with an "error" message
+struct ApiError(StatusCode, String);
+
+#[derive(Serialize, ToSchema)]
+pub(crate) struct ErrorBody {
+    error: String,
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(ErrorBody { error: self.1 })).into_response()
+    }
+}
+
+pub fn new_api_router() -> axum::Router {
+    axum::Router::new()
+        .route("/:game", post(create_handler))
+        .route("/:game/:id", get(state_handler))
+        .route("/:game/:id/moves", post(move_handler))
+}
+
+fn check_game(game: &str) -> Result<(), ApiError> {
+    if GAMES.contains(&game) {
+        Ok(())
+    } else {
+        Err(ApiError(
+            StatusCode::NOT_FOUND,
+            format!("Unknown game \"{game}\""),
+        ))
+    }
+}
+
+// an empty body stands for `{}`
+fn parse_body<T: DeserializeOwned + Default>(body: &Bytes) -> Result<T, ApiError> {
+    if body.iter().all(u8::is_ascii_whitespace) {
+        return Ok(T::default());
+    }
+    serde_json::from_slice(body)
+        .map_err(|err| ApiError(StatusCode::BAD_REQUEST, format!("Invalid JSON body: {err}")))
+}
+
+fn find_game<'a>(
+    games: &'a mut HashMap<String, Entry>,
+    game: &str,
+    id: &str,
+) -> Result<&'a mut Entry, ApiError> {
+    check_game(game)?;
+    let entry = games
+        .get_mut(id)
+        .filter(|entry| entry.game.name() == game)
+        .ok_or(ApiError(
+            StatusCode::NOT_FOUND,
+            format!("No {game} game with id {id}"),
+        ))?;
+    entry.touched = Instant::now();
+    Ok(entry)
+}
+
+/// Starts a game, the body may be empty
+#[utoipa::path(
+    post,
+    path = "/api/v1/{game}",
+    params(("game" = String, Path, description = "ttt, connect or battleship")),
+    request_body(content = NewGame, content_type = "application/json"),
+    responses(
+        (status = 201, description = "The new game", body = GameResponse),
+        (status = 400, description = "Invalid options", body = ErrorBody),
+        (status = 404, description = "Unknown game", body = ErrorBody),
+    )
+)]
+async fn create_handler(Path(game): Path<String>, body: Bytes) -> Result<Response, ApiError> {
+    check_game(&game)?;
+    let options: NewGame = parse_body(&body)?;
+    let name = options.name.clone();
+    let new_game =
+        ApiGame::new(&game, options).map_err(|err| ApiError(StatusCode::BAD_REQUEST, err))?;
+    let state = new_game.state();
+
+    metrics::game_started(&game, metrics::SERVER);
+    let id = format!("{:016x}", rand::thread_rng().gen::<u64>());
+    let mut games = API_GAMES.lock().unwrap();
+    games.retain(|_, entry| entry.touched.elapsed() < GAME_TTL);
+    games.insert(
+        id.clone(),
+        Entry {
+            game: new_game,
+            name,
+            started: Instant::now(),
+            touched: Instant::now(),
+            busy: false,
+        },
+    );
+    Ok((
+        StatusCode::CREATED,
+        [(header::LOCATION, format!("/api/v1/{game}/{id}"))],
+        Json(GameResponse {
+            id,
+            state,
+            server_move: None,
+        }),
+    )
+        .into_response())
+}
+
+/// The state of a game
+#[utoipa::path(
+    get,
+    path = "/api/v1/{game}/{id}",
+    params(
+        ("game" = String, Path, description = "ttt, connect or battleship"),
+        ("id" = String, Path, description = "Id returned when the game was created"),
+    ),
+    responses(
+        (status = 200, description = "The game", body = GameResponse),
+        (status = 404, description = "Unknown game or id", body = ErrorBody),
+    )
+)]
+async fn state_handler(
+    Path((game, id)): Path<(String, String)>,
+) -> Result<Json<GameResponse>, ApiError> {
+    let mut games = API_GAMES.lock().unwrap();
+    let entry = find_game(&mut games, &game, &id)?;
+    Ok(Json(GameResponse {
+        state: entry.game.state(),
+        id,
+        server_move: None,
+    }))
+}
+
+/// Plays a move and the server's reply
+#[utoipa::path(
+    post,
+    path = "/api/v1/{game}/{id}/moves",
+    params(
+        ("game" = String, Path, description = "ttt, connect or battleship"),
+        ("id" = String, Path, description = "Id returned when the game was created"),
+    ),
+    request_body(content = MoveBody, content_type = "application/json"),
+    responses(
+        (status = 200, description = "The game after both moves", body = GameResponse),
+        (status = 400, description = "Invalid body or illegal move", body = ErrorBody),
+        (status = 404, description = "Unknown game or id", body = ErrorBody),
+        (status = 409, description = "The game is over or another move is being played", body = ErrorBody),
+    )
+)]
+async fn move_handler(
+    Path((game, id)): Path<(String, String)>,
+    body: Bytes,
+) -> Result<Json<GameResponse>, ApiError> {
+    let body: Option<MoveBody> = parse_body(&body)?;
+    let mv = body
+        .ok_or(ApiError(
+            StatusCode::BAD_REQUEST,
+            "Missing \"move\"".to_owned(),
+        ))?
+        .r#move;
+    // the server's reply can take a while, it is worked out on a copy of the
+    // game without holding the lock
+    let (mut played, name, started) = {
+        let mut games = API_GAMES.lock().unwrap();
+        let entry = find_game(&mut games, &game, &id)?;
+        if entry.game.outcome().is_over() {
+            return Err(ApiError(StatusCode::CONFLICT, "Game is over".to_owned()));
+        }
+        if entry.busy {
+            return Err(ApiError(
+                StatusCode::CONFLICT,
+                "Another move is being played".to_owned(),
+            ));
+        }
+        entry.busy = true;
+        (entry.game.clone(), entry.name.clone(), entry.started)
+    };
+    let _busy = BusyGuard(id.clone());
+    let reply = tokio::task::spawn_blocking(move || {
+        let server_move = played.play(&mv);
+        (played, server_move)
+    })
+    .await;
+    {
+        let mut games = API_GAMES.lock().unwrap();
+        if let Some(entry) = games.get_mut(&id) {
+            // a move that breaks the rules leaves the game as it was
+            if let Ok((played, Ok(_))) = &reply {
+                entry.game = played.clone();
+            }
+        }
+    }
+    let (played, server_move) = reply.map_err(|err| {
+        ApiError(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("The move failed: {err}"),
+        )
+    })?;
+    let server_move = server_move.map_err(|err| ApiError(StatusCode::BAD_REQUEST, err))?;
+
+    // only a finished game has a result, only a named player's is saved
+    let record = played.record(name.as_deref().unwrap_or_default());
+    if let Some(result) = GameResult::from_record(&record, stats::seconds_since(started)) {
+        metrics::game_finished(&result.game, Some(result.result));
+        stats::save_all(vec![result]).await;
+    }
+    Ok(Json(GameResponse {
+        state: played.state(),
+        id,
+        server_move,
+    }))
+}
@@ -1,84 +1,346 @@
-use axum::{response::Html, routing::get};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::Path,
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+    routing::get,
+};
+use once_cell::sync::Lazy;
+use rand::Rng;
 use serde::Deserialize;
 use tower_sessions::Session;
+use utoipa::ToSchema;
 
 use axum::{body::Body, extract::Request};
 use axum_typed_multipart::TryFromMultipart;
 
 use ui_components::battleship::*;
+use ui_components::game::Game;
+use ui_components::html;
 
-use crate::lib::parse_form;
+use crate::lib::{parse_form, player_token, Watchers};
+use crate::stats;
 
-#[derive(Debug, Clone, Deserialize, TryFromMultipart)]
+#[derive(Debug, Clone, Deserialize, TryFromMultipart, ToSchema)]
 struct GameForm {
     name: Option<String>,
     r#move: Option<String>,
     play_again: Option<String>,
+    mode: Option<String>,
+    fleet: Option<String>,
+    seed: Option<String>,
+    no_touch: Option<String>,
 }
 
 pub fn new_battleship_router() -> axum::Router {
-    axum::Router::new().route("/", get(get_form_handler).post(post_form_handler))
+    axum::Router::new()
+        .route("/", get(get_form_handler).post(post_form_handler))
+        .route("/watch/:id", get(watch_handler))
 }
 
+/// The start form
+#[utoipa::path(
+    get,
+    path = "/battleship.php",
+    responses((status = 200, description = "Start form", content_type = "text/html", body = String))
+)]
 async fn get_form_handler() -> Html<&'static str> {
     Html(BATTLESHIP_GET_PAGE)
 }
 
 const NAME_KEY: &str = "name";
-const MOVES_KEY: &str = "moves_left";
 const BOARD_KEY: &str = "board";
+const MODE_KEY: &str = "mode";
+const PLAYER_BOARD_KEY: &str = "player_board";
+const OPTIONS_KEY: &str = "fleet_options";
+const WATCH_KEY: &str = "watch_id";
+// games nobody has played for this long are no longer shown to spectators
+const SHARED_TTL: Duration = Duration::from_secs(3600);
+
+// the latest position of a game, kept for its spectators
+struct Shared {
+    name: String,
+    board: Board,
+    // the player's fleet in a two-sided game
+    player_board: Option<Board>,
+    touched: Instant,
+    watchers: Watchers,
+}
 
-async fn post_form_handler(session: Session, req: Request<Body>) -> Html<String> {
+// by watch id, each session shares its games under one id
+static SHARED: Lazy<Mutex<HashMap<String, Shared>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+// publishes the game to its spectators, returns the line for the player's page
+async fn share(
+    session: &Session,
+    name: &str,
+    board: &Board,
+    player_board: Option<&Board>,
+) -> String {
+    let id = match session.get::<String>(WATCH_KEY).await.unwrap() {
+        Some(id) => id,
+        None => {
+            let id = format!("{:016x}", rand::thread_rng().gen::<u64>());
+            session.insert(WATCH_KEY, &id).await.unwrap();
+            id
+        }
+    };
+    let mut shared = SHARED.lock().unwrap();
+    shared.retain(|_, game| game.touched.elapsed() < SHARED_TTL);
+    let watchers = shared
+        .remove(&id)
+        .map(|game| game.watchers)
+        .unwrap_or_default();
+    let line = watch_line(&id, watchers.count());
+    shared.insert(
+        id,
+        Shared {
+            name: name.to_owned(),
+            board: board.clone(),
+            player_board: player_board.cloned(),
+            touched: Instant::now(),
+            watchers,
+        },
+    );
+    line
+}
+
+/// The read-only page of a game for spectators
+#[utoipa::path(
+    get,
+    path = "/battleship.php/watch/{id}",
+    params(("id" = String, Path, description = "Watch id from the player's page")),
+    responses(
+        (status = 200, description = "Spectator page", content_type = "text/html", body = String),
+        (status = 404, description = "No game with this id", content_type = "text/html", body = String),
+    )
+)]
+async fn watch_handler(session: Session, Path(id): Path<String>) -> Response {
+    let token = player_token(&session).await;
+    let own_game = session.get::<String>(WATCH_KEY).await.unwrap() == Some(id.clone());
+    let mut shared = SHARED.lock().unwrap();
+    let Some(game) = shared.get_mut(&id) else {
+        return (
+            StatusCode::NOT_FOUND,
+            Html(format!("No game to watch with id {}", html::escape(&id))),
+        )
+            .into_response();
+    };
+    // the player peeking at their own game is not a spectator
+    if !own_game {
+        game.watchers.see(&token);
+    }
+    Html(make_spectator_page(
+        &game.name,
+        &game.board,
+        game.player_board.as_ref(),
+        game.watchers.count(),
+    ))
+    .into_response()
+}
+
+/// Starts a game when `name` is given, otherwise fires at `move` in the
+/// session's game
+#[utoipa::path(
+    post,
+    path = "/battleship.php",
+    request_body(content = inline(GameForm), content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 200, description = "Game page", content_type = "text/html", body = String),
+        (status = 400, description = "Malformed move, seed or fleet", content_type = "text/html", body = String),
+    )
+)]
+async fn post_form_handler(session: Session, req: Request<Body>) -> Response {
     let form: GameForm = match parse_form(req).await {
         Ok(form) => form,
-        Err(err_page) => return err_page,
+        Err(err_page) => return err_page.into_response(),
+    };
+
+    // a malformed move is rejected before anything else happens
+    let target = match form
+        .r#move
+        .as_deref()
+        .map(|mv| Coordinate::parse(mv, ROWS, COLS))
+    {
+        None => None,
+        Some(Ok(target)) => Some(target),
+        Some(Err(err)) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Html(format!("Invalid move: {}", html::escape(&err))),
+            )
+                .into_response()
+        }
     };
 
     // process name
     if let Some(name) = form.name {
         session.insert(NAME_KEY, name).await.unwrap();
+        if let Some(mode) = form.mode.as_deref() {
+            // choosing a mode starts a fresh game
+            let mode = Mode::parse(mode).unwrap_or_default();
+            session.insert(MODE_KEY, mode).await.unwrap();
+            let _: Option<Board> = session.remove(BOARD_KEY).await.unwrap();
+            let _: Option<Board> = session.remove(PLAYER_BOARD_KEY).await.unwrap();
+            let seed = match form.seed.as_deref().map(str::trim) {
+                None | Some("") => None,
+                Some(seed) => match seed.parse() {
+                    Ok(seed) => Some(seed),
+                    Err(_) => {
+                        return (
+                            StatusCode::BAD_REQUEST,
+                            Html(format!("Invalid seed: {}", html::escape(seed))),
+                        )
+                            .into_response()
+                    }
+                },
+            };
+            let options = FleetOptions {
+                seed,
+                no_touch: form.no_touch.is_some(),
+            };
+            session.insert(OPTIONS_KEY, options).await.unwrap();
+            let fleet = form.fleet.unwrap_or_default();
+            if mode == Mode::TwoSided && !fleet.trim().is_empty() {
+                match place_fleet(ROWS, COLS, &SHIPS, &fleet, options.no_touch) {
+                    Ok(fleet) => {
+                        let player_board = Board::from_fleet(fleet, None);
+                        session
+                            .insert(PLAYER_BOARD_KEY, player_board)
+                            .await
+                            .unwrap();
+                    }
+                    Err(err) => {
+                        return (
+                            StatusCode::BAD_REQUEST,
+                            Html(format!("Invalid fleet: {}", html::escape(&err))),
+                        )
+                            .into_response()
+                    }
+                }
+            }
+        }
     } else if form.play_again.is_some() {
-        // must reset move and board before loading it for rendering
-        let _: Option<i32> = session.remove(MOVES_KEY).await.unwrap();
-        let _: Option<Vec<Vec<Tile>>> = session.remove(BOARD_KEY).await.unwrap();
+        // must reset board before loading it for rendering
+        let _: Option<Board> = session.remove(BOARD_KEY).await.unwrap();
+        let _: Option<Board> = session.remove(PLAYER_BOARD_KEY).await.unwrap();
+        // a new game gets a new seed
+        let mut options: FleetOptions = session.get(OPTIONS_KEY).await.unwrap().unwrap_or_default();
+        options.seed = None;
+        session.insert(OPTIONS_KEY, options).await.unwrap();
     }
 
-    let mut moves_left = session
-        .get(MOVES_KEY)
-        .await
-        .unwrap()
-        .unwrap_or(((COLS as f64) * (ROWS as f64) * 0.60).ceil() as i32);
+    let options: FleetOptions = session.get(OPTIONS_KEY).await.unwrap().unwrap_or_default();
+    let mode: Mode = session.get(MODE_KEY).await.unwrap().unwrap_or_default();
+    if mode == Mode::TwoSided {
+        return two_sided_handler(session, options, target).await;
+    }
 
-    let mut board = session
-        .get(BOARD_KEY)
+    let name = session
+        .get(NAME_KEY)
         .await
         .unwrap()
-        .unwrap_or(create_battleship_game(ROWS, COLS, &SHIPS));
-
-    // make move
-    if let Some(move_str) = form.r#move {
-        let mut it = move_str.split(',');
-        let (i, j): (usize, usize) = (
-            it.next().unwrap().parse().unwrap(),
-            it.next().unwrap().parse().unwrap(),
-        );
-        match board[i][j] {
-            Ship => board[i][j] = Hit,
-            Untried => board[i][j] = Miss,
-            _ => {}
+        .unwrap_or("".to_owned());
+
+    let mut board: Board = match session.get(BOARD_KEY).await.unwrap() {
+        Some(board) => board,
+        None => match Board::one_sided(&options) {
+            Ok(board) => {
+                stats::start(&session, "battleship", &name).await;
+                board
+            }
+            Err(err) => return Html(err).into_response(),
+        },
+    };
+
+    // make move, a repeated or late move costs nothing and is explained
+    let mut message = String::new();
+    if let Some(target) = target {
+        if let Err(err) = board.apply_move(target.into()) {
+            message = err;
         }
-        moves_left -= 1;
     }
 
     // update session
-    session.insert(MOVES_KEY, moves_left).await.unwrap();
     session.insert(BOARD_KEY, &board).await.unwrap();
 
+    if board.outcome().is_over() {
+        stats::finish(&session, &one_sided_record(&name, &board)).await;
+    }
+
+    let watch = share(&session, &name, &board, None).await;
+    Html(make_board_page(name, board, &message, &watch)).into_response()
+}
+
+async fn two_sided_handler(
+    session: Session,
+    options: FleetOptions,
+    target: Option<Coordinate>,
+) -> Response {
     let name = session
         .get(NAME_KEY)
         .await
         .unwrap()
         .unwrap_or("".to_owned());
 
-    Html(make_board_page(name, board, moves_left))
+    let mut board: Board = match session.get(BOARD_KEY).await.unwrap() {
+        Some(board) => board,
+        None => match Board::two_sided(&options) {
+            Ok(board) => {
+                stats::start(&session, "battleship", &name).await;
+                board
+            }
+            Err(err) => return Html(err).into_response(),
+        },
+    };
+
+    let mut player_board: Board = match session.get(PLAYER_BOARD_KEY).await.unwrap() {
+        Some(board) => board,
+        None => match Board::player_fleet(&board, &options) {
+            Ok(board) => board,
+            Err(err) => return Html(err).into_response(),
+        },
+    };
+
+    // make move, then the server fires back unless the player just won. a
+    // rejected move does not give the server a free shot
+    let mut message = String::new();
+    if let Some(target) = target {
+        let result = if player_board.outcome().is_over() {
+            Err("Game is over".to_string())
+        } else {
+            board.apply_move(target.into())
+        };
+        match result {
+            Ok(()) if !board.outcome().is_over() => {
+                server_fire(&mut player_board);
+            }
+            Ok(()) => {}
+            Err(err) => message = err,
+        }
+    }
+
+    // update session
+    session.insert(BOARD_KEY, &board).await.unwrap();
+    session
+        .insert(PLAYER_BOARD_KEY, &player_board)
+        .await
+        .unwrap();
+
+    if two_sided_outcome(&board, &player_board).is_over() {
+        stats::finish(&session, &two_sided_record(&name, &board, &player_board)).await;
+    }
+
+    let watch = share(&session, &name, &board, Some(&player_board)).await;
+    Html(make_two_sided_page(
+        name,
+        board,
+        player_board,
+        &message,
+        &watch,
+    ))
+    .into_response()
 }
@@ -1,29 +1,97 @@
-use axum::{body::Body, extract::Request, response::Html, routing::get};
+use axum::{
+    body::Body,
+    extract::Request,
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+    routing::get,
+};
 use axum_typed_multipart::TryFromMultipart;
 use serde::Deserialize;
+use tower_sessions::Session;
+use utoipa::ToSchema;
 
-use crate::lib::parse_form;
+use crate::lib::{check_signature, parse_form, SIGNER};
+use crate::stats;
 use ui_components::connect;
 
-#[derive(Debug, Clone, Deserialize, TryFromMultipart)]
+#[derive(Debug, Clone, Deserialize, TryFromMultipart, ToSchema)]
 struct GameForm {
     name: String,
     #[serde(default)]
     #[form_data(default)]
     board: String,
+    depth: Option<u32>,
+    variant: Option<String>,
+    moves: Option<String>,
+    sig: Option<String>,
 }
 
 pub fn new_connect_router() -> axum::Router {
     axum::Router::new().route("/", get(get_form_handler).post(post_form_handler))
 }
 
+/// The start form
+#[utoipa::path(
+    get,
+    path = "/connect.php",
+    responses((status = 200, description = "Start form", content_type = "text/html", body = String))
+)]
 async fn get_form_handler() -> Html<String> {
     Html(connect::get_form_html())
 }
 
-async fn post_form_handler(req: Request<Body>) -> Html<String> {
+/// Plays the column chosen on `board` and the server's reply
+#[utoipa::path(
+    post,
+    path = "/connect.php",
+    request_body(content = inline(GameForm), content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 200, description = "Game page", content_type = "text/html", body = String),
+        (status = 400, description = "Invalid board or signature", content_type = "text/html", body = String),
+    )
+)]
+async fn post_form_handler(session: Session, req: Request<Body>) -> Response {
     match parse_form::<GameForm>(req).await {
-        Ok(form) => Html(connect::accept_from_html(form.name, form.board)),
-        Err(error_page) => error_page,
+        Ok(form) => {
+            let variant = form
+                .variant
+                .as_deref()
+                .and_then(connect::variant_from_name)
+                .unwrap_or(connect::Variant::CONNECT_FOUR);
+            let depth = form.depth.unwrap_or(connect::DEFAULT_DEPTH);
+            if !check_signature(&SIGNER, &form.board, form.sig.as_deref(), |signer, sig| {
+                connect::verify_board(signer, variant, &form.board, sig)
+            }) {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Html("Board signature does not match".to_owned()),
+                )
+                    .into_response();
+            }
+            if form.board.is_empty() {
+                stats::start(&session, "connect", &form.name).await;
+            }
+            match connect::accept_from_html(
+                form.name.clone(),
+                form.board,
+                form.moves.as_deref().unwrap_or_default(),
+                depth,
+                variant,
+                Some(SIGNER.clone()),
+            ) {
+                Ok((page, finished)) => {
+                    if let Some(record) = finished {
+                        stats::finish(&session, &record).await;
+                    }
+                    Html(page).into_response()
+                }
+                Err(err) => (
+                    StatusCode::BAD_REQUEST,
+                    Html(connect::parse_error_html(form.name, depth, variant, err)),
+                )
+                    .into_response(),
+            }
+        }
+        Err(error_page) => error_page.into_response(),
     }
 }
@@ -0,0 +1,75 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use axum::{
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+};
+
+use crate::{metrics, stats};
+
+// set once a shutdown signal arrives, from then on /readyz turns traffic away
+pub static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+
+pub fn new_health_router() -> axum::Router {
+    axum::Router::new().route("/", get(health_handler))
+}
+
+pub fn new_ready_router() -> axum::Router {
+    axum::Router::new().route("/", get(ready_handler))
+}
+
+pub fn new_metrics_router() -> axum::Router {
+    axum::Router::new().route("/", get(metrics_handler))
+}
+
+/// Whether the server is running at all
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    responses((status = 200, description = "The server is up", content_type = "text/plain", body = String))
+)]
+async fn health_handler() -> &'static str {
+    "ok"
+}
+
+/// Whether the server should be sent traffic: the stats database answers and
+/// it is not shutting down
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    responses(
+        (status = 200, description = "Ready for traffic", content_type = "text/plain", body = String),
+        (status = 503, description = "Shutting down or the database is unavailable", content_type = "text/plain", body = String),
+    )
+)]
+async fn ready_handler() -> Response {
+    if SHUTTING_DOWN.load(Ordering::Relaxed) {
+        return (StatusCode::SERVICE_UNAVAILABLE, "shutting down").into_response();
+    }
+    match stats::check() {
+        Ok(()) => "ready".into_response(),
+        Err(err) => {
+            tracing::error!("not ready, the stats database failed: {err}");
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "stats database unavailable",
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Request, session and game counters in the Prometheus text format
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    responses((status = 200, description = "Metrics", content_type = "text/plain", body = String))
+)]
+async fn metrics_handler() -> Response {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics::render(),
+    )
+        .into_response()
+}
@@ -1,3 +1,6 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
 use axum::{
     body::Body,
     extract::{FromRequest, Request},
@@ -5,6 +8,34 @@ use axum::{
     Form,
 };
 use axum_typed_multipart::{TryFromMultipart, TypedMultipart};
+use once_cell::sync::Lazy;
+use rand::Rng;
+use tower_sessions::Session;
+use ui_components::signing::Signer;
+
+const PLAYER_KEY: &str = "player";
+// a spectator without a websocket counts as watching for this long after a
+// page view, their pages refresh well within it
+const WATCH_WINDOW: Duration = Duration::from_secs(10);
+
+// signs and checks the boards the ttt and connect pages hand to the client.
+// without a state_secret the key is made up at startup, so boards are always
+// checked but links stop working when the server restarts
+pub static SIGNER: Lazy<Signer> = Lazy::new(|| match &crate::config().state_secret {
+    Some(secret) => Signer::new(secret),
+    None => Signer::new(&format!("{:032x}", rand::thread_rng().gen::<u128>())),
+});
+
+// a board sent back by the client must carry the signature the server gave
+// it, only the empty board of a new game needs none
+pub fn check_signature(
+    signer: &Signer,
+    board: &str,
+    sig: Option<&str>,
+    verify: impl Fn(&Signer, &str) -> bool,
+) -> bool {
+    board.is_empty() || verify(signer, sig.unwrap_or_default())
+}
 
 pub async fn parse_form<T>(req: Request<Body>) -> Result<T, Html<String>>
 where
@@ -33,3 +64,110 @@ where
         None => Err(Html("No content-type".to_owned())),
     }
 }
+
+// the token that ties this browser session to its seats and spectator views
+pub async fn player_token(session: &Session) -> String {
+    if let Some(token) = session.get::<String>(PLAYER_KEY).await.unwrap() {
+        return token;
+    }
+    let token = format!("{:032x}", rand::thread_rng().gen::<u128>());
+    session.insert(PLAYER_KEY, &token).await.unwrap();
+    token
+}
+
+// the people watching one game, by player token
+#[derive(Debug, Default)]
+pub struct Watchers {
+    // the last page view of each spectator
+    seen: HashMap<String, Instant>,
+    // open websockets of each spectator
+    connected: HashMap<String, usize>,
+}
+
+impl Watchers {
+    pub fn see(&mut self, token: &str) {
+        self.seen.retain(|_, seen| seen.elapsed() < WATCH_WINDOW);
+        self.seen.insert(token.to_owned(), Instant::now());
+    }
+
+    pub fn connect(&mut self, token: &str) {
+        *self.connected.entry(token.to_owned()).or_default() += 1;
+    }
+
+    pub fn disconnect(&mut self, token: &str) {
+        if let Some(sockets) = self.connected.get_mut(token) {
+            *sockets -= 1;
+            if *sockets == 0 {
+                self.connected.remove(token);
+            }
+        }
+    }
+
+    // a spectator who sat down to play is no longer watching
+    pub fn forget(&mut self, token: &str) {
+        self.seen.remove(token);
+        self.connected.remove(token);
+    }
+
+    pub fn count(&self) -> usize {
+        let polling = self
+            .seen
+            .iter()
+            .filter(|(token, seen)| {
+                seen.elapsed() < WATCH_WINDOW && !self.connected.contains_key(*token)
+            })
+            .count();
+        polling + self.connected.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ui_components::{connect, ttt};
+
+    use super::*;
+
+    const BOARD: &str = "X  O      ";
+
+    fn check(signer: &Signer, board: &str, sig: Option<&str>) -> bool {
+        check_signature(signer, board, sig, |signer, sig| {
+            ttt::verify_board(signer, ttt::Variant::TIC_TAC_TOE, board, sig)
+        })
+    }
+
+    #[test]
+    fn boards_need_the_signature_they_were_given() {
+        let signer = Signer::new("secret");
+        let sig = signer.sign("ttt:3x3", BOARD);
+        assert!(check(&signer, BOARD, Some(&sig)));
+        assert!(!check(&signer, BOARD, None));
+        assert!(!check(&signer, BOARD, Some("")));
+        assert!(!check(&Signer::new("other"), BOARD, Some(&sig)));
+        // a new game has nothing to sign
+        assert!(check(&signer, "", None));
+    }
+
+    #[test]
+    fn signatures_belong_to_one_game() {
+        let signer = Signer::new("secret");
+        let sig = signer.sign("ttt:3x3", BOARD);
+        assert!(ttt::verify_board(
+            &signer,
+            ttt::Variant::TIC_TAC_TOE,
+            BOARD,
+            &sig
+        ));
+        assert!(!ttt::verify_board(
+            &signer,
+            ttt::Variant::TIC_TAC_TOE_4X4,
+            BOARD,
+            &sig
+        ));
+        assert!(!connect::verify_board(
+            &signer,
+            connect::Variant::CONNECT_FOUR,
+            BOARD,
+            &sig
+        ));
+    }
+}
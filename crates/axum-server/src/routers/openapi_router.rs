@@ -0,0 +1,165 @@
+use axum::{routing::get, Json};
+use utoipa::OpenApi;
+
+use crate::routers::{
+    api_router, battleship_router, connect_router, health_router, record_router, room_router,
+    stats_router, ttt_router,
+};
+use ui_components::{api, game, ttt};
+
+// every route of the server, built from the handlers' `utoipa::path`
+// attributes and the form types they take
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Games server",
+        description = "Tic-tac-toe, Connect-4 and Battleship against the server or other players"
+    ),
+    paths(
+        ttt_router::get_handler,
+        connect_router::get_form_handler,
+        connect_router::post_form_handler,
+        battleship_router::get_form_handler,
+        battleship_router::post_form_handler,
+        battleship_router::watch_handler,
+        record_router::get_form_handler,
+        record_router::post_form_handler,
+        room_router::lobby_handler,
+        room_router::create_handler,
+        room_router::find_handler,
+        room_router::room_handler,
+        room_router::watch_handler,
+        room_router::join_handler,
+        room_router::move_handler,
+        room_router::ws_handler,
+        api_router::create_handler,
+        api_router::state_handler,
+        api_router::move_handler,
+        stats_router::leaderboard_handler,
+        stats_router::player_handler,
+        health_router::health_handler,
+        health_router::ready_handler,
+        health_router::metrics_handler,
+        openapi_handler,
+    ),
+    components(schemas(
+        api::NewGame,
+        api::GameState,
+        api_router::GameResponse,
+        api_router::MoveBody,
+        api_router::ErrorBody,
+        game::Outcome,
+        game::Player,
+        ttt::Difficulty,
+    ))
+)]
+pub struct ApiDoc;
+
+pub fn new_openapi_router() -> axum::Router {
+    axum::Router::new().route("/", get(openapi_handler))
+}
+
+/// This document
+#[utoipa::path(
+    get,
+    path = "/openapi.json",
+    responses((status = 200, description = "OpenAPI 3 document", content_type = "application/json"))
+)]
+async fn openapi_handler() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use axum::{
+        body::Body,
+        extract::Request,
+        http::{header, Method, StatusCode},
+    };
+    use tower::ServiceExt;
+    use utoipa::OpenApi;
+
+    use super::ApiDoc;
+    use crate::routers::routes;
+
+    // the methods the server routes for the documented `path`. no route takes
+    // TRACE, so the router answers it with 405 and lists the methods it does
+    // take, without running a handler
+    async fn routed_methods(path: &str) -> BTreeSet<(String, String)> {
+        // "/room.php/{code}/move" is requested as "/room.php/x/move"
+        let uri = path
+            .split('/')
+            .map(|segment| {
+                if segment.starts_with('{') {
+                    "x"
+                } else {
+                    segment
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+        let app = routes()
+            .into_iter()
+            .fold(axum::Router::new(), |app, (prefix, router)| {
+                app.nest(prefix, router)
+            });
+        let request = Request::builder()
+            .method(Method::TRACE)
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        if response.status() != StatusCode::METHOD_NOT_ALLOWED {
+            return BTreeSet::new();
+        }
+        let allow = response.headers()[header::ALLOW].to_str().unwrap();
+        allow
+            .split(',')
+            .map(|method| method.trim().to_ascii_lowercase())
+            // axum answers HEAD wherever it answers GET
+            .filter(|method| method != "head")
+            .map(|method| (method, path.to_owned()))
+            .collect()
+    }
+
+    fn documented_routes() -> BTreeSet<(String, String)> {
+        ApiDoc::openapi()
+            .paths
+            .paths
+            .iter()
+            .flat_map(|(path, item)| {
+                item.operations.keys().map(|method| {
+                    // serializes as the lowercase method name
+                    let method = serde_json::to_value(method).unwrap();
+                    (method.as_str().unwrap().to_owned(), path.clone())
+                })
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn every_route_is_documented() {
+        let documented = documented_routes();
+        let paths: BTreeSet<&String> = documented.iter().map(|(_, path)| path).collect();
+        for (prefix, _) in routes() {
+            assert!(
+                paths.iter().any(|path| path.starts_with(prefix)),
+                "nothing under {prefix} is documented"
+            );
+        }
+
+        let mut routed = BTreeSet::new();
+        for path in paths {
+            routed.extend(routed_methods(path).await);
+        }
+        let undocumented: Vec<_> = routed.difference(&documented).collect();
+        assert!(
+            undocumented.is_empty(),
+            "routes missing from the OpenAPI document: {undocumented:?}"
+        );
+        let stale: Vec<_> = documented.difference(&routed).collect();
+        assert!(stale.is_empty(), "OpenAPI paths without a route: {stale:?}");
+    }
+}
@@ -0,0 +1,67 @@
+use axum::{
+    body::Body,
+    extract::Request,
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+    routing::get,
+};
+use axum_typed_multipart::TryFromMultipart;
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+use crate::lib::parse_form;
+use ui_components::{html, record};
+
+#[derive(Debug, Clone, Deserialize, TryFromMultipart, ToSchema)]
+struct RecordForm {
+    // an uploaded record file
+    file: Option<String>,
+    // a pasted record, also used by the replay buttons
+    record: Option<String>,
+    ply: Option<usize>,
+}
+
+pub fn new_record_router() -> axum::Router {
+    axum::Router::new().route("/", get(get_form_handler).post(post_form_handler))
+}
+
+/// The upload form
+#[utoipa::path(
+    get,
+    path = "/record.php",
+    responses((status = 200, description = "Upload form", content_type = "text/html", body = String))
+)]
+async fn get_form_handler() -> Html<String> {
+    Html(record::get_form_html())
+}
+
+/// Replays an uploaded or pasted game record
+#[utoipa::path(
+    post,
+    path = "/record.php",
+    request_body(content = inline(RecordForm), content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "Replay page", content_type = "text/html", body = String),
+        (status = 400, description = "Invalid game record", content_type = "text/html", body = String),
+    )
+)]
+async fn post_form_handler(req: Request<Body>) -> Response {
+    let form: RecordForm = match parse_form(req).await {
+        Ok(form) => form,
+        Err(error_page) => return error_page.into_response(),
+    };
+    // an empty file input still sends an empty file
+    let text = form
+        .file
+        .filter(|file| !file.trim().is_empty())
+        .or(form.record)
+        .unwrap_or_default();
+    match record::replay_html(&text, form.ply) {
+        Ok(page) => Html(page).into_response(),
+        Err(err) => (
+            StatusCode::BAD_REQUEST,
+            Html(format!("Invalid game record: {}", html::escape(&err))),
+        )
+            .into_response(),
+    }
+}
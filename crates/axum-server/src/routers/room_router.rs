@@ -0,0 +1,404 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use axum::{
+    body::Body,
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Path, Query, Request,
+    },
+    http::StatusCode,
+    response::{Html, IntoResponse, Redirect, Response},
+    routing::{get, post},
+};
+use axum_typed_multipart::TryFromMultipart;
+use once_cell::sync::Lazy;
+use rand::Rng;
+use serde::Deserialize;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tower_sessions::Session;
+use utoipa::{IntoParams, ToSchema};
+
+use ui_components::html;
+use ui_components::room::{self, Room, RoomUpdate};
+
+use crate::lib::{parse_form, player_token, Watchers};
+use crate::{metrics, stats};
+
+// rooms nobody has looked at for this long are dropped
+const ROOM_TTL: Duration = Duration::from_secs(3600);
+// no 0/O or 1/I, invite codes are read out loud
+const CODE_CHARS: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const CODE_LENGTH: usize = 6;
+// updates a slow websocket may fall behind by before it misses some
+const UPDATE_BUFFER: usize = 16;
+
+struct Entry {
+    room: Room,
+    touched: Instant,
+    // when the second player sat down
+    started: Option<Instant>,
+    // json `RoomUpdate`s for every websocket watching the room
+    updates: broadcast::Sender<String>,
+    watchers: Watchers,
+}
+
+impl Entry {
+    fn update(&self, event: &'static str, seat: Option<usize>, mv: Option<&str>) -> RoomUpdate {
+        self.room.update(event, seat, mv, self.watchers.count())
+    }
+
+    fn publish(&self, event: &'static str, seat: Option<usize>, mv: Option<&str>) {
+        let update = serde_json::to_string(&self.update(event, seat, mv)).unwrap();
+        // nobody may be listening, that is fine
+        let _ = self.updates.send(update);
+    }
+
+    // tells everyone when the number of spectators changed from `before`
+    fn publish_watchers(&self, before: usize) {
+        if self.watchers.count() != before {
+            self.publish("watchers", None, None);
+        }
+    }
+
+    // a visitor who is not seated is watching
+    fn see(&mut self, token: &str) {
+        if self.room.seat_of(token).is_none() {
+            let before = self.watchers.count();
+            self.watchers.see(token);
+            self.publish_watchers(before);
+        }
+    }
+}
+
+static ROOMS: Lazy<Mutex<HashMap<String, Entry>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Debug, Clone, Deserialize, TryFromMultipart, ToSchema)]
+struct CreateForm {
+    name: String,
+    game: String,
+}
+
+#[derive(Debug, Clone, Deserialize, TryFromMultipart, ToSchema)]
+struct JoinForm {
+    name: String,
+}
+
+#[derive(Debug, Clone, Deserialize, TryFromMultipart, ToSchema)]
+struct MoveForm {
+    r#move: String,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct CodeQuery {
+    code: String,
+}
+
+pub fn new_room_router() -> axum::Router {
+    axum::Router::new()
+        .route("/", get(lobby_handler).post(create_handler))
+        .route("/join", get(find_handler))
+        .route("/:code", get(room_handler))
+        .route("/:code/watch", get(watch_handler))
+        .route("/:code/join", post(join_handler))
+        .route("/:code/move", post(move_handler))
+}
+
+pub fn new_ws_router() -> axum::Router {
+    axum::Router::new().route("/:code", get(ws_handler))
+}
+
+fn new_code(rooms: &HashMap<String, Entry>) -> String {
+    let mut rng = rand::thread_rng();
+    loop {
+        let code: String = (0..CODE_LENGTH)
+            .map(|_| CODE_CHARS[rng.gen_range(0..CODE_CHARS.len())] as char)
+            .collect();
+        if !rooms.contains_key(&code) {
+            return code;
+        }
+    }
+}
+
+fn no_room(code: &str) -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        Html(format!(
+            r#"No room with code {}. <a href="/room.php">Back to the lobby</a>"#,
+            html::escape(code)
+        )),
+    )
+        .into_response()
+}
+
+fn room_url(code: &str) -> String {
+    format!("/room.php/{code}")
+}
+
+/// The lobby with the forms to create and join rooms
+#[utoipa::path(
+    get,
+    path = "/room.php",
+    responses((status = 200, description = "Lobby", content_type = "text/html", body = String))
+)]
+async fn lobby_handler() -> Html<String> {
+    Html(room::lobby_html())
+}
+
+/// Creates a room and seats its creator
+#[utoipa::path(
+    post,
+    path = "/room.php",
+    request_body(content = inline(CreateForm), content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 303, description = "Redirect to the new room"),
+        (status = 400, description = "Unknown game", content_type = "text/html", body = String),
+    )
+)]
+async fn create_handler(session: Session, req: Request<Body>) -> Response {
+    let form: CreateForm = match parse_form(req).await {
+        Ok(form) => form,
+        Err(error_page) => return error_page.into_response(),
+    };
+    let mut room = match Room::new(&form.game) {
+        Ok(room) => room,
+        Err(err) => return (StatusCode::BAD_REQUEST, Html(html::escape(&err))).into_response(),
+    };
+    let token = player_token(&session).await;
+    let _ = room.join(&token, &form.name);
+
+    let mut rooms = ROOMS.lock().unwrap();
+    rooms.retain(|_, entry| entry.touched.elapsed() < ROOM_TTL);
+    let code = new_code(&rooms);
+    rooms.insert(
+        code.clone(),
+        Entry {
+            room,
+            touched: Instant::now(),
+            started: None,
+            updates: broadcast::channel(UPDATE_BUFFER).0,
+            watchers: Watchers::default(),
+        },
+    );
+    Redirect::to(&room_url(&code)).into_response()
+}
+
+/// Redirects the lobby's join form to the room
+#[utoipa::path(
+    get,
+    path = "/room.php/join",
+    params(CodeQuery),
+    responses((status = 303, description = "Redirect to the room"))
+)]
+async fn find_handler(Query(query): Query<CodeQuery>) -> Redirect {
+    Redirect::to(&room_url(query.code.trim().to_uppercase().as_str()))
+}
+
+/// The room as the visitor sees it, with a join form while a seat is open
+#[utoipa::path(
+    get,
+    path = "/room.php/{code}",
+    params(("code" = String, Path, description = "Invite code")),
+    responses(
+        (status = 200, description = "Room page", content_type = "text/html", body = String),
+        (status = 404, description = "No room with this code", content_type = "text/html", body = String),
+    )
+)]
+async fn room_handler(session: Session, Path(code): Path<String>) -> Response {
+    let token = player_token(&session).await;
+    let mut rooms = ROOMS.lock().unwrap();
+    let Some(entry) = rooms.get_mut(&code) else {
+        return no_room(&code);
+    };
+    entry.touched = Instant::now();
+    entry.see(&token);
+    let seat = entry.room.seat_of(&token);
+    let watchers = entry.watchers.count();
+    Html(entry.room.html(&code, seat, "", watchers)).into_response()
+}
+
+/// The read-only room page for spectators
+#[utoipa::path(
+    get,
+    path = "/room.php/{code}/watch",
+    params(("code" = String, Path, description = "Invite code")),
+    responses(
+        (status = 200, description = "Spectator page", content_type = "text/html", body = String),
+        (status = 404, description = "No room with this code", content_type = "text/html", body = String),
+    )
+)]
+async fn watch_handler(session: Session, Path(code): Path<String>) -> Response {
+    let token = player_token(&session).await;
+    let mut rooms = ROOMS.lock().unwrap();
+    let Some(entry) = rooms.get_mut(&code) else {
+        return no_room(&code);
+    };
+    entry.touched = Instant::now();
+    entry.see(&token);
+    Html(entry.room.watch_html(&code, entry.watchers.count())).into_response()
+}
+
+/// Takes the open seat
+#[utoipa::path(
+    post,
+    path = "/room.php/{code}/join",
+    params(("code" = String, Path, description = "Invite code")),
+    request_body(content = inline(JoinForm), content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 303, description = "Redirect to the room"),
+        (status = 404, description = "No room with this code", content_type = "text/html", body = String),
+        (status = 409, description = "The room is full", content_type = "text/html", body = String),
+    )
+)]
+async fn join_handler(session: Session, Path(code): Path<String>, req: Request<Body>) -> Response {
+    let form: JoinForm = match parse_form(req).await {
+        Ok(form) => form,
+        Err(error_page) => return error_page.into_response(),
+    };
+    let token = player_token(&session).await;
+    let mut rooms = ROOMS.lock().unwrap();
+    let Some(entry) = rooms.get_mut(&code) else {
+        return no_room(&code);
+    };
+    entry.touched = Instant::now();
+    let seated = entry.room.seat_of(&token).is_some();
+    match entry.room.join(&token, &form.name) {
+        Ok(seat) => {
+            if !seated {
+                if entry.room.is_full() {
+                    entry.started = Some(Instant::now());
+                    metrics::game_started(entry.room.game(), metrics::ROOM);
+                }
+                entry.watchers.forget(&token);
+                entry.publish("joined", Some(seat), None);
+            }
+            Redirect::to(&room_url(&code)).into_response()
+        }
+        Err(err) => (
+            StatusCode::CONFLICT,
+            Html(entry.room.html(&code, None, &err, entry.watchers.count())),
+        )
+            .into_response(),
+    }
+}
+
+/// Plays a move for the visitor's seat
+#[utoipa::path(
+    post,
+    path = "/room.php/{code}/move",
+    params(("code" = String, Path, description = "Invite code")),
+    request_body(content = inline(MoveForm), content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 303, description = "Redirect to the room"),
+        (status = 404, description = "No room with this code", content_type = "text/html", body = String),
+        (status = 409, description = "Not the visitor's turn or an illegal move", content_type = "text/html", body = String),
+    )
+)]
+async fn move_handler(session: Session, Path(code): Path<String>, req: Request<Body>) -> Response {
+    let form: MoveForm = match parse_form(req).await {
+        Ok(form) => form,
+        Err(error_page) => return error_page.into_response(),
+    };
+    let token = player_token(&session).await;
+    // the results are saved once the room is unlocked
+    let finished = {
+        let mut rooms = ROOMS.lock().unwrap();
+        let Some(entry) = rooms.get_mut(&code) else {
+            return no_room(&code);
+        };
+        entry.touched = Instant::now();
+        let seat = entry.room.seat_of(&token);
+        if let Err(err) = entry.room.play(&token, &form.r#move) {
+            return (
+                StatusCode::CONFLICT,
+                Html(entry.room.html(&code, seat, &err, entry.watchers.count())),
+            )
+                .into_response();
+        }
+        entry.publish("move", seat, Some(&form.r#move));
+        if entry.room.outcome().is_over() {
+            entry.publish("game_over", None, None);
+            metrics::game_finished(entry.room.game(), None);
+            entry
+                .room
+                .results(entry.started.and_then(stats::seconds_since))
+        } else {
+            vec![]
+        }
+    };
+    stats::save_all(finished).await;
+    // redirect so a refresh does not resubmit the move
+    Redirect::to(&room_url(&code)).into_response()
+}
+
+// pushes every update of a room to a browser, starting with its current
+// state. a socket of someone who is not seated counts as a spectator
+/// A WebSocket of JSON room updates
+#[utoipa::path(
+    get,
+    path = "/ws/{code}",
+    params(("code" = String, Path, description = "Invite code")),
+    responses(
+        (status = 101, description = "Switches to a WebSocket that sends the current state and then every change"),
+        (status = 404, description = "No room with this code", content_type = "text/html", body = String),
+    )
+)]
+async fn ws_handler(ws: WebSocketUpgrade, session: Session, Path(code): Path<String>) -> Response {
+    let token = player_token(&session).await;
+    let (updates, state, watching) = {
+        let mut rooms = ROOMS.lock().unwrap();
+        let Some(entry) = rooms.get_mut(&code) else {
+            return no_room(&code);
+        };
+        let watching = entry.room.seat_of(&token).is_none();
+        if watching {
+            let before = entry.watchers.count();
+            entry.watchers.connect(&token);
+            entry.publish_watchers(before);
+        }
+        let state = serde_json::to_string(&entry.update("state", None, None)).unwrap();
+        (entry.updates.subscribe(), state, watching)
+    };
+    ws.on_upgrade(move |socket| async move {
+        forward_updates(socket, updates, state).await;
+        if watching {
+            if let Some(entry) = ROOMS.lock().unwrap().get_mut(&code) {
+                let before = entry.watchers.count();
+                entry.watchers.disconnect(&token);
+                entry.publish_watchers(before);
+            }
+        }
+    })
+}
+
+async fn forward_updates(
+    mut socket: WebSocket,
+    mut updates: broadcast::Receiver<String>,
+    state: String,
+) {
+    if socket.send(Message::Text(state)).await.is_err() {
+        return;
+    }
+    loop {
+        tokio::select! {
+            update = updates.recv() => match update {
+                Ok(update) => {
+                    if socket.send(Message::Text(update)).await.is_err() {
+                        break;
+                    }
+                }
+                // the next update carries the whole state anyway
+                Err(RecvError::Lagged(_)) => continue,
+                // the room expired
+                Err(RecvError::Closed) => break,
+            },
+            // anything the browser sends is ignored until it closes
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_)) | Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
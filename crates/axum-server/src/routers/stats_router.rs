@@ -0,0 +1,68 @@
+use axum::{
+    extract::Path,
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+    routing::get,
+};
+
+use ui_components::stats;
+
+use crate::stats::{leaders, results};
+
+pub fn new_stats_router() -> axum::Router {
+    axum::Router::new()
+        .route("/", get(leaderboard_handler))
+        .route("/:name", get(player_handler))
+}
+
+// the database is read off the async runtime
+async fn read<T: Send + 'static>(
+    read: impl FnOnce() -> Result<T, String> + Send + 'static,
+) -> Result<T, String> {
+    tokio::task::spawn_blocking(read)
+        .await
+        .map_err(|err| err.to_string())?
+}
+
+fn unavailable(err: String) -> Response {
+    tracing::error!("failed to read the stats database: {err}");
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Html("Statistics are unavailable".to_owned()),
+    )
+        .into_response()
+}
+
+/// The best players of each game
+#[utoipa::path(
+    get,
+    path = "/leaderboard",
+    responses(
+        (status = 200, description = "Leaderboard page", content_type = "text/html", body = String),
+        (status = 500, description = "The database could not be read", content_type = "text/html", body = String),
+    )
+)]
+async fn leaderboard_handler() -> Response {
+    match read(leaders).await {
+        Ok(leaders) => Html(stats::leaderboard_html(&leaders)).into_response(),
+        Err(err) => unavailable(err),
+    }
+}
+
+/// Win rates, streaks and recent games of one player
+#[utoipa::path(
+    get,
+    path = "/leaderboard/{name}",
+    params(("name" = String, Path, description = "Player name")),
+    responses(
+        (status = 200, description = "Player page", content_type = "text/html", body = String),
+        (status = 500, description = "The database could not be read", content_type = "text/html", body = String),
+    )
+)]
+async fn player_handler(Path(name): Path<String>) -> Response {
+    let player = name.clone();
+    match read(move || results(&player)).await {
+        Ok(results) => Html(stats::player_html(&name, &results)).into_response(),
+        Err(err) => unavailable(err),
+    }
+}
@@ -1,22 +1,90 @@
-use axum::{extract::Query, response::Html, routing::get};
+use axum::{
+    extract::Query,
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+    routing::get,
+};
 use serde::Deserialize;
+use tower_sessions::Session;
+use ui_components::ttt::{Difficulty, Variant};
+use utoipa::IntoParams;
 
-#[derive(Deserialize)]
+use crate::lib::{check_signature, SIGNER};
+use crate::stats;
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 struct StartGameForm {
     name: Option<String>,
     board: Option<String>,
+    #[serde(default)]
+    difficulty: Difficulty,
+    variant: Option<String>,
+    moves: Option<String>,
+    sig: Option<String>,
 }
 
 pub fn new_ttt_router() -> axum::Router {
     axum::Router::new().route("/", get(get_handler))
 }
 
-async fn get_handler(Query(query): Query<StartGameForm>) -> Html<String> {
+/// The start form without a name, otherwise the game page for the board
+#[utoipa::path(
+    get,
+    path = "/ttt.php",
+    params(StartGameForm),
+    responses(
+        (status = 200, description = "Start form or game page", content_type = "text/html", body = String),
+        (status = 400, description = "Invalid board or signature", content_type = "text/html", body = String),
+    )
+)]
+async fn get_handler(session: Session, Query(query): Query<StartGameForm>) -> Response {
     match query.name {
         Some(name) => {
             let board = query.board.unwrap_or_default();
-            Html(ui_components::ttt::accept_from_html(name, board))
+            let variant = query
+                .variant
+                .as_deref()
+                .and_then(ui_components::ttt::variant_from_name)
+                .unwrap_or(Variant::TIC_TAC_TOE);
+            if !check_signature(&SIGNER, &board, query.sig.as_deref(), |signer, sig| {
+                ui_components::ttt::verify_board(signer, variant, &board, sig)
+            }) {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Html("Board signature does not match".to_owned()),
+                )
+                    .into_response();
+            }
+            if board.is_empty() {
+                stats::start(&session, "ttt", &name).await;
+            }
+            match ui_components::ttt::accept_from_html(
+                name.clone(),
+                board,
+                query.moves.as_deref().unwrap_or_default(),
+                query.difficulty,
+                variant,
+                Some(SIGNER.clone()),
+            ) {
+                Ok((page, finished)) => {
+                    if let Some(record) = finished {
+                        stats::finish(&session, &record).await;
+                    }
+                    Html(page).into_response()
+                }
+                Err(err) => (
+                    StatusCode::BAD_REQUEST,
+                    Html(ui_components::ttt::parse_error_html(
+                        name,
+                        query.difficulty,
+                        variant,
+                        err,
+                    )),
+                )
+                    .into_response(),
+            }
         }
-        None => Html(ui_components::ttt::get_form_html()),
+        None => Html(ui_components::ttt::get_form_html()).into_response(),
     }
 }
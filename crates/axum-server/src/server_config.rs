@@ -0,0 +1,351 @@
+// the server's settings: config.toml, then APP_ environment variables, then
+// the defaults below for anything neither of them sets
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::Path;
+
+use axum::http::HeaderValue;
+use config::{Config, Environment, File, Map};
+use serde::{Deserialize, Deserializer, Serialize};
+
+pub const DEFAULT_PATH: &str = "config.toml";
+// APP_HTTP_PORT=8080 overrides http_port
+const ENV_PREFIX: &str = "APP";
+const SESSION_STORES: [&str; 3] = ["memory", "file", "sqlite"];
+// settings read from the environment as comma separated lists
+const LIST_KEYS: [&str; 4] = [
+    "body_log_content_types",
+    "body_log_redact",
+    "body_log_redact_cookies",
+    "body_log_skip_paths",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    // "127.0.0.1" or [127, 0, 0, 1]
+    #[serde(deserialize_with = "deserialize_ip")]
+    pub ip: IpAddr,
+    pub http_port: u16,
+    // sent back in the x-cse356 header of every response, required
+    pub submission_id: String,
+    // key for signing client-held boards, a random one is made up at startup
+    // without it
+    pub state_secret: Option<String>,
+    // sqlite file the results of finished games are kept in
+    pub stats_db: String,
+    // "memory", "file" or "sqlite", see session_store.rs
+    pub session_store: String,
+    // the session directory or database, each store has its own default
+    pub session_path: Option<String>,
+    // how often expired sessions are removed from a file or sqlite store
+    pub session_cleanup_secs: u64,
+    // pem files, https is served on https_port when both are set and http_port
+    // only redirects there. SIGHUP reloads them
+    pub tls_cert: Option<String>,
+    pub tls_key: Option<String>,
+    pub https_port: u16,
+    // how long open requests get to finish after SIGTERM or SIGINT
+    pub shutdown_grace_secs: u64,
+    // how long /readyz reports the shutdown before connections are refused,
+    // so load balancers stop sending traffic first
+    pub shutdown_delay_secs: u64,
+    // request and response bodies logged at DEBUG, see body_log.rs
+    pub body_log: bool,
+    // how much of each body is logged, the rest is passed on unread
+    pub body_log_max_bytes: usize,
+    // media types whose bodies are logged, matched without parameters
+    pub body_log_content_types: Vec<String>,
+    // form fields and json keys whose values are hidden
+    pub body_log_redact: Vec<String>,
+    // cookies whose values are hidden in the Cookie and Set-Cookie headers
+    pub body_log_redact_cookies: Vec<String>,
+    // share of requests logged, from 0 to 1
+    pub body_log_sample_rate: f64,
+    // paths starting with any of these are never logged
+    pub body_log_skip_paths: Vec<String>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            ip: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            http_port: 80,
+            submission_id: String::new(),
+            state_secret: None,
+            stats_db: "stats.db".to_owned(),
+            session_store: "memory".to_owned(),
+            session_path: None,
+            session_cleanup_secs: 600,
+            tls_cert: None,
+            tls_key: None,
+            https_port: 443,
+            shutdown_grace_secs: 30,
+            shutdown_delay_secs: 5,
+            body_log: true,
+            body_log_max_bytes: 4096,
+            body_log_content_types: vec![
+                "application/json".to_owned(),
+                "application/x-www-form-urlencoded".to_owned(),
+            ],
+            body_log_redact: vec!["name".to_owned()],
+            body_log_redact_cookies: vec!["id".to_owned()],
+            body_log_sample_rate: 1.0,
+            body_log_skip_paths: vec!["/ws".to_owned(), "/metrics".to_owned()],
+        }
+    }
+}
+
+fn deserialize_ip<'de, D: Deserializer<'de>>(deserializer: D) -> Result<IpAddr, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Ip {
+        Octets([u8; 4]),
+        Text(String),
+    }
+    match Ip::deserialize(deserializer)? {
+        Ip::Octets(octets) => Ok(IpAddr::from(octets)),
+        Ip::Text(text) => text
+            .trim()
+            .parse()
+            .map_err(|_| serde::de::Error::custom(format!("ip \"{text}\" is not an IP address"))),
+    }
+}
+
+// the APP_ variables that name a setting. unknown keys are an error in the
+// file, but other APP_ variables such as APP_ENV belong to something else
+fn environment(variables: impl IntoIterator<Item = (String, String)>) -> Environment {
+    let settings = serde_json::to_value(ServerConfig::default()).unwrap();
+    let prefix = format!("{}_", ENV_PREFIX.to_ascii_lowercase());
+    let variables: Map<String, String> = variables
+        .into_iter()
+        .filter(|(key, _)| {
+            key.to_ascii_lowercase()
+                .strip_prefix(&prefix)
+                .is_some_and(|setting| settings.get(setting).is_some())
+        })
+        .collect();
+    LIST_KEYS.into_iter().fold(
+        Environment::with_prefix(ENV_PREFIX)
+            .try_parsing(true)
+            .list_separator(",")
+            .source(Some(variables)),
+        Environment::with_list_parse_key,
+    )
+}
+
+impl ServerConfig {
+    // reads the file given with --config, which must exist, or config.toml,
+    // which may be missing, and the environment on top of it
+    pub fn load(path: Option<&Path>) -> Result<ServerConfig, String> {
+        ServerConfig::load_with(path, std::env::vars())
+    }
+
+    fn load_with(
+        path: Option<&Path>,
+        variables: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<ServerConfig, String> {
+        let required = path.is_some();
+        let path = path.unwrap_or(Path::new(DEFAULT_PATH));
+        if required && !path.is_file() {
+            return Err(format!("{} does not exist", path.display()));
+        }
+        let config: ServerConfig = Config::builder()
+            .add_source(File::from(path).required(required))
+            .add_source(environment(variables))
+            .build()
+            .and_then(Config::try_deserialize)
+            .map_err(|err| format!("{}: {err}", path.display()))?;
+        config.validate().map_err(|errors| {
+            format!(
+                "{} is not valid:\n  {}",
+                path.display(),
+                errors.join("\n  ")
+            )
+        })?;
+        Ok(config)
+    }
+
+    // every problem with the settings, not just the first
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = vec![];
+        if self.submission_id.trim().is_empty() {
+            errors.push("submission_id is required".to_owned());
+        } else if HeaderValue::from_str(&self.submission_id).is_err() {
+            errors.push("submission_id must be printable ASCII to fit in a header".to_owned());
+        }
+        if self.http_port == 0 {
+            errors.push("http_port must be between 1 and 65535".to_owned());
+        }
+        if self.state_secret.as_deref().is_some_and(str::is_empty) {
+            errors.push("state_secret must not be empty, leave it out for a random one".to_owned());
+        }
+        if self.stats_db.trim().is_empty() {
+            errors.push("stats_db must name a file".to_owned());
+        }
+        if !SESSION_STORES.contains(&self.session_store.as_str()) {
+            errors.push(format!(
+                "session_store is \"{}\", expected one of {}",
+                self.session_store,
+                SESSION_STORES.join(", ")
+            ));
+        }
+        if self.session_cleanup_secs == 0 {
+            errors.push("session_cleanup_secs must be at least 1".to_owned());
+        }
+        match (&self.tls_cert, &self.tls_key) {
+            (Some(_), None) => errors.push("tls_cert is set, tls_key is needed too".to_owned()),
+            (None, Some(_)) => errors.push("tls_key is set, tls_cert is needed too".to_owned()),
+            _ => {}
+        }
+        for (name, path) in [("tls_cert", &self.tls_cert), ("tls_key", &self.tls_key)] {
+            if let Some(path) = path.as_deref().filter(|path| !Path::new(path).is_file()) {
+                errors.push(format!("{name} \"{path}\" is not a file"));
+            }
+        }
+        if self.tls_enabled() && (self.https_port == 0 || self.https_port == self.http_port) {
+            errors.push(
+                "https_port must be between 1 and 65535 and differ from http_port".to_owned(),
+            );
+        }
+        if self.body_log_max_bytes == 0 {
+            errors.push(
+                "body_log_max_bytes must be at least 1, set body_log = false to log no bodies"
+                    .to_owned(),
+            );
+        }
+        if !(0.0..=1.0).contains(&self.body_log_sample_rate) {
+            errors.push("body_log_sample_rate must be between 0 and 1".to_owned());
+        }
+        if self
+            .body_log_skip_paths
+            .iter()
+            .any(|path| !path.starts_with('/'))
+        {
+            errors.push("body_log_skip_paths must all start with /".to_owned());
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    pub fn tls_enabled(&self) -> bool {
+        self.tls_cert.is_some() && self.tls_key.is_some()
+    }
+
+    // the settings as config.toml, secrets left out
+    pub fn to_toml(&self) -> String {
+        let mut shown = self.clone();
+        if shown.state_secret.is_some() {
+            shown.state_secret = Some("(hidden)".to_owned());
+        }
+        toml::to_string(&shown).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a settings file of its own for each test
+    fn config_file(name: &str, contents: &str) -> std::path::PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("server-config-{}-{name}.toml", std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn variables(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn the_defaults_need_only_a_submission_id() {
+        assert_eq!(
+            ServerConfig::default().validate(),
+            Err(vec!["submission_id is required".to_owned()])
+        );
+        let config = ServerConfig {
+            submission_id: "abc".to_owned(),
+            ..ServerConfig::default()
+        };
+        assert_eq!(config.validate(), Ok(()));
+    }
+
+    #[test]
+    fn every_error_is_reported() {
+        let config = ServerConfig {
+            http_port: 0,
+            session_store: "redis".to_owned(),
+            tls_cert: Some("/no/such/cert.pem".to_owned()),
+            body_log_sample_rate: 2.0,
+            ..ServerConfig::default()
+        };
+        assert_eq!(
+            config.validate(),
+            Err(vec![
+                "submission_id is required".to_owned(),
+                "http_port must be between 1 and 65535".to_owned(),
+                "session_store is \"redis\", expected one of memory, file, sqlite".to_owned(),
+                "tls_cert is set, tls_key is needed too".to_owned(),
+                "tls_cert \"/no/such/cert.pem\" is not a file".to_owned(),
+                "body_log_sample_rate must be between 0 and 1".to_owned(),
+            ])
+        );
+
+        let path = config_file("invalid", "http_port = 0\n");
+        let err = ServerConfig::load_with(Some(&path), vec![]).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert!(err.contains("submission_id is required"), "{err}");
+        assert!(err.contains("http_port must be"), "{err}");
+    }
+
+    #[test]
+    fn the_environment_overrides_the_file() {
+        let path = config_file("override", "submission_id = \"abc\"\nhttp_port = 8000\n");
+        let config = ServerConfig::load_with(
+            Some(&path),
+            variables(&[("APP_HTTP_PORT", "8080"), ("APP_ENV", "production")]),
+        );
+        std::fs::remove_file(&path).unwrap();
+        let config = config.unwrap();
+        assert_eq!(config.http_port, 8080);
+        assert_eq!(config.submission_id, "abc");
+    }
+
+    #[test]
+    fn unknown_keys_in_the_file_are_an_error() {
+        let path = config_file("unknown", "submission_id = \"abc\"\nenv = \"production\"\n");
+        let err = ServerConfig::load_with(Some(&path), vec![]).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert!(err.contains("env"), "{err}");
+    }
+
+    #[test]
+    fn lists_are_split_on_commas() {
+        let path = config_file("lists", "submission_id = \"abc\"\n");
+        let config = ServerConfig::load_with(
+            Some(&path),
+            variables(&[
+                ("APP_BODY_LOG_SKIP_PATHS", "/ws,/metrics,/health"),
+                ("APP_BODY_LOG_REDACT", "name,password"),
+            ]),
+        );
+        std::fs::remove_file(&path).unwrap();
+        let config = config.unwrap();
+        assert_eq!(config.body_log_skip_paths, ["/ws", "/metrics", "/health"]);
+        assert_eq!(config.body_log_redact, ["name", "password"]);
+    }
+
+    #[test]
+    fn a_named_file_must_exist() {
+        let path = std::env::temp_dir().join("server-config-missing.toml");
+        let err = ServerConfig::load_with(Some(&path), variables(&[("APP_SUBMISSION_ID", "abc")]))
+            .unwrap_err();
+        assert_eq!(err, format!("{} does not exist", path.display()));
+    }
+}
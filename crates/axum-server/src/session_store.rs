@@ -0,0 +1,449 @@
+// where sessions are kept, chosen by `session_store` in config.toml
+//
+// "memory" loses every session on restart. "file" keeps one json file per
+// session in a directory and "sqlite" one row per session in a database, both
+// survive restarts and can be shared by several server processes.
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use rusqlite::{params, Connection, OptionalExtension};
+use tower_sessions::cookie::time::OffsetDateTime;
+use tower_sessions::session::{Id, Record};
+use tower_sessions::session_store::{self, ExpiredDeletion, SessionStore};
+use tower_sessions::MemoryStore;
+
+use crate::metrics;
+
+type Result<T> = session_store::Result<T>;
+
+fn backend(err: impl ToString) -> session_store::Error {
+    session_store::Error::Backend(err.to_string())
+}
+
+#[derive(Debug, Clone)]
+pub enum Store {
+    Memory(MemoryStore),
+    File(FileStore),
+    Sqlite(SqliteStore),
+}
+
+impl Store {
+    // `kind` is "memory", "file" or "sqlite", `path` the directory or database
+    // file and defaults to "sessions" or "sessions.db"
+    pub fn open(kind: &str, path: Option<&str>) -> std::result::Result<Store, String> {
+        match kind {
+            "memory" => Ok(Store::Memory(MemoryStore::default())),
+            "file" => FileStore::open(path.unwrap_or("sessions")).map(Store::File),
+            "sqlite" => SqliteStore::open(path.unwrap_or("sessions.db")).map(Store::Sqlite),
+            kind => Err(format!(
+                "Unknown session store \"{kind}\", expected memory, file or sqlite"
+            )),
+        }
+    }
+
+    // removes expired sessions every `period`, the memory store drops them
+    // on its own when they are next loaded
+    pub fn spawn_cleanup(&self, period: Duration) {
+        let store = self.clone();
+        if let Store::Memory(_) = store {
+            return;
+        }
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                if let Err(err) = store.delete_expired().await {
+                    tracing::error!("failed to delete expired sessions: {err}");
+                }
+            }
+        });
+    }
+
+    // called once the server has stopped. every session is already written
+    // when its request ends, this drops the expired ones and leaves the
+    // sqlite database in a single file
+    pub async fn close(&self) {
+        if let Store::Memory(_) = self {
+            tracing::info!("sessions were kept in memory and are lost");
+            return;
+        }
+        if let Err(err) = self.delete_expired().await {
+            tracing::error!("failed to delete expired sessions: {err}");
+        }
+        if let Store::Sqlite(store) = self {
+            if let Err(err) = store.checkpoint() {
+                tracing::error!("failed to checkpoint the session database: {err}");
+            }
+        }
+        tracing::info!("sessions saved");
+    }
+}
+
+// every store reports the sessions it sees to /metrics
+#[async_trait]
+impl SessionStore for Store {
+    async fn save(&self, record: &Record) -> Result<()> {
+        match self {
+            Store::Memory(store) => store.save(record).await,
+            Store::File(store) => store.save(record).await,
+            Store::Sqlite(store) => store.save(record).await,
+        }?;
+        metrics::session_active(record.id, record.expiry_date);
+        Ok(())
+    }
+
+    async fn load(&self, id: &Id) -> Result<Option<Record>> {
+        let record = match self {
+            Store::Memory(store) => store.load(id).await,
+            Store::File(store) => store.load(id).await,
+            Store::Sqlite(store) => store.load(id).await,
+        }?;
+        match &record {
+            Some(record) => metrics::session_active(record.id, record.expiry_date),
+            None => metrics::session_ended(id),
+        }
+        Ok(record)
+    }
+
+    async fn delete(&self, id: &Id) -> Result<()> {
+        match self {
+            Store::Memory(store) => store.delete(id).await,
+            Store::File(store) => store.delete(id).await,
+            Store::Sqlite(store) => store.delete(id).await,
+        }?;
+        metrics::session_ended(id);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ExpiredDeletion for Store {
+    async fn delete_expired(&self) -> Result<()> {
+        match self {
+            Store::Memory(_) => Ok(()),
+            Store::File(store) => store.delete_expired().await,
+            Store::Sqlite(store) => store.delete_expired().await,
+        }
+    }
+}
+
+fn is_expired(record: &Record) -> bool {
+    record.expiry_date <= OffsetDateTime::now_utc()
+}
+
+// one json file per session, named after its id
+#[derive(Debug, Clone)]
+pub struct FileStore {
+    dir: PathBuf,
+}
+
+impl FileStore {
+    pub fn open(dir: &str) -> std::result::Result<FileStore, String> {
+        std::fs::create_dir_all(dir)
+            .map_err(|err| format!("Unable to create session directory {dir}: {err}"))?;
+        Ok(FileStore { dir: dir.into() })
+    }
+
+    // ids are url-safe base64, fine as file names
+    fn path(&self, id: &Id) -> PathBuf {
+        self.dir.join(format!("{id}.json"))
+    }
+
+    async fn read(&self, path: &PathBuf) -> Result<Option<Record>> {
+        match tokio::fs::read(path).await {
+            Ok(bytes) => match serde_json::from_slice(&bytes) {
+                Ok(record) => Ok(Some(record)),
+                // a damaged file is as good as an expired session
+                Err(err) => {
+                    tracing::warn!("unreadable session file {}: {err}", path.display());
+                    Ok(None)
+                }
+            },
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(backend(err)),
+        }
+    }
+
+    async fn remove(&self, path: &PathBuf) -> Result<()> {
+        match tokio::fs::remove_file(path).await {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(backend(err)),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl SessionStore for FileStore {
+    async fn save(&self, record: &Record) -> Result<()> {
+        let json = serde_json::to_vec(record)
+            .map_err(|err| session_store::Error::Encode(err.to_string()))?;
+        // written aside and renamed so other processes never read half a file
+        let path = self.path(&record.id);
+        let partial = path.with_extension(format!("{}.tmp", std::process::id()));
+        tokio::fs::write(&partial, json).await.map_err(backend)?;
+        tokio::fs::rename(&partial, &path).await.map_err(backend)
+    }
+
+    async fn load(&self, id: &Id) -> Result<Option<Record>> {
+        Ok(self
+            .read(&self.path(id))
+            .await?
+            .filter(|record| !is_expired(record)))
+    }
+
+    async fn delete(&self, id: &Id) -> Result<()> {
+        self.remove(&self.path(id)).await
+    }
+}
+
+#[async_trait]
+impl ExpiredDeletion for FileStore {
+    async fn delete_expired(&self) -> Result<()> {
+        let mut entries = tokio::fs::read_dir(&self.dir).await.map_err(backend)?;
+        while let Some(entry) = entries.next_entry().await.map_err(backend)? {
+            let path = entry.path();
+            if path.extension().is_none_or(|extension| extension != "json") {
+                continue;
+            }
+            // unreadable files are removed too
+            match self.read(&path).await {
+                Ok(Some(record)) if !is_expired(&record) => {}
+                _ => self.remove(&path).await?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// one row per session, expiry dates in unix seconds
+#[derive(Debug, Clone)]
+pub struct SqliteStore {
+    db: Arc<Mutex<Connection>>,
+}
+
+impl SqliteStore {
+    pub fn open(path: &str) -> std::result::Result<SqliteStore, String> {
+        let db = Connection::open(path)
+            .map_err(|err| format!("Unable to open session database {path}: {err}"))?;
+        // other processes may be writing too, wait for them instead of failing
+        db.busy_timeout(Duration::from_secs(5))
+            .and_then(|_| {
+                db.execute_batch(
+                    "PRAGMA journal_mode = WAL;
+                    CREATE TABLE IF NOT EXISTS sessions (
+                        id TEXT PRIMARY KEY NOT NULL,
+                        data TEXT NOT NULL,
+                        expiry_date INTEGER NOT NULL
+                    );
+                    CREATE INDEX IF NOT EXISTS sessions_expiry_date ON sessions (expiry_date);",
+                )
+            })
+            .map_err(|err| format!("Unable to set up session database {path}: {err}"))?;
+        Ok(SqliteStore {
+            db: Arc::new(Mutex::new(db)),
+        })
+    }
+
+    // moves the write-ahead log into the database file
+    fn checkpoint(&self) -> rusqlite::Result<()> {
+        self.db
+            .lock()
+            .unwrap()
+            .execute_batch("PRAGMA wal_checkpoint(TRUNCATE);")
+    }
+}
+
+#[async_trait]
+impl SessionStore for SqliteStore {
+    async fn save(&self, record: &Record) -> Result<()> {
+        let data = serde_json::to_string(&record.data)
+            .map_err(|err| session_store::Error::Encode(err.to_string()))?;
+        self.db
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT INTO sessions (id, data, expiry_date) VALUES (?1, ?2, ?3)
+                ON CONFLICT (id) DO UPDATE SET data = ?2, expiry_date = ?3",
+                params![
+                    record.id.to_string(),
+                    data,
+                    record.expiry_date.unix_timestamp()
+                ],
+            )
+            .map_err(backend)?;
+        Ok(())
+    }
+
+    async fn load(&self, id: &Id) -> Result<Option<Record>> {
+        let row: Option<(String, i64)> = self
+            .db
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT data, expiry_date FROM sessions WHERE id = ?1 AND expiry_date > ?2",
+                params![id.to_string(), OffsetDateTime::now_utc().unix_timestamp()],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+            .map_err(backend)?;
+        let Some((data, expiry_date)) = row else {
+            return Ok(None);
+        };
+        Ok(Some(Record {
+            id: *id,
+            data: serde_json::from_str(&data)
+                .map_err(|err| session_store::Error::Decode(err.to_string()))?,
+            expiry_date: OffsetDateTime::from_unix_timestamp(expiry_date)
+                .map_err(|err| session_store::Error::Decode(err.to_string()))?,
+        }))
+    }
+
+    async fn delete(&self, id: &Id) -> Result<()> {
+        self.db
+            .lock()
+            .unwrap()
+            .execute(
+                "DELETE FROM sessions WHERE id = ?1",
+                params![id.to_string()],
+            )
+            .map_err(backend)?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ExpiredDeletion for SqliteStore {
+    async fn delete_expired(&self) -> Result<()> {
+        self.db
+            .lock()
+            .unwrap()
+            .execute(
+                "DELETE FROM sessions WHERE expiry_date <= ?1",
+                params![OffsetDateTime::now_utc().unix_timestamp()],
+            )
+            .map_err(backend)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use tower_sessions::cookie::time::Duration as TimeDuration;
+
+    use super::*;
+
+    // a new empty directory or database path under the system temp dir
+    fn temp_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!(
+            "session-store-{}-{name}-{:x}",
+            std::process::id(),
+            rand::random::<u32>()
+        ));
+        path.to_str().unwrap().to_owned()
+    }
+
+    // whole seconds, the sqlite store keeps no more
+    fn record(expires_in: TimeDuration) -> Record {
+        let expiry = OffsetDateTime::now_utc() + expires_in;
+        Record {
+            id: Id::default(),
+            data: HashMap::from([("name".to_owned(), serde_json::json!("alice"))]),
+            expiry_date: OffsetDateTime::from_unix_timestamp(expiry.unix_timestamp()).unwrap(),
+        }
+    }
+
+    // the tests every store must pass
+    async fn check_store(store: impl ExpiredDeletion) {
+        let live = record(TimeDuration::hours(1));
+        store.save(&live).await.unwrap();
+        assert_eq!(store.load(&live.id).await.unwrap(), Some(live.clone()));
+
+        let mut changed = live.clone();
+        changed
+            .data
+            .insert("board".to_owned(), serde_json::json!([1, 2]));
+        store.save(&changed).await.unwrap();
+        assert_eq!(store.load(&live.id).await.unwrap(), Some(changed));
+
+        let expired = record(TimeDuration::seconds(-1));
+        store.save(&expired).await.unwrap();
+        assert_eq!(store.load(&expired.id).await.unwrap(), None);
+
+        store.delete(&live.id).await.unwrap();
+        assert_eq!(store.load(&live.id).await.unwrap(), None);
+        // deleting twice is fine
+        store.delete(&live.id).await.unwrap();
+        assert_eq!(store.load(&Id::default()).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn file_store() {
+        let dir = temp_path("files");
+        check_store(FileStore::open(&dir).unwrap()).await;
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn sqlite_store() {
+        let path = temp_path("db");
+        check_store(SqliteStore::open(&path).unwrap()).await;
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{path}{suffix}"));
+        }
+    }
+
+    #[tokio::test]
+    async fn file_store_drops_expired_and_unreadable_files() {
+        let dir = temp_path("expired");
+        let store = FileStore::open(&dir).unwrap();
+        let live = record(TimeDuration::hours(1));
+        let expired = record(TimeDuration::seconds(-1));
+        store.save(&live).await.unwrap();
+        store.save(&expired).await.unwrap();
+        let corrupt = Id::default();
+        std::fs::write(store.path(&corrupt), "{ not json").unwrap();
+        assert_eq!(store.load(&corrupt).await.unwrap(), None);
+        // other files in the directory are left alone
+        std::fs::write(store.dir.join("notes.txt"), "keep").unwrap();
+
+        store.delete_expired().await.unwrap();
+        let mut left: Vec<String> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        left.sort();
+        let mut expected = vec![format!("{}.json", live.id), "notes.txt".to_owned()];
+        expected.sort();
+        assert_eq!(left, expected);
+        assert_eq!(store.load(&live.id).await.unwrap(), Some(live));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn sqlite_store_drops_expired_rows() {
+        let path = temp_path("expired-db");
+        let store = SqliteStore::open(&path).unwrap();
+        let live = record(TimeDuration::hours(1));
+        store.save(&live).await.unwrap();
+        store
+            .save(&record(TimeDuration::seconds(-1)))
+            .await
+            .unwrap();
+        store.delete_expired().await.unwrap();
+        let rows: i64 = store
+            .db
+            .lock()
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM sessions", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(rows, 1);
+        assert_eq!(store.load(&live.id).await.unwrap(), Some(live));
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{path}{suffix}"));
+        }
+    }
+}
@@ -0,0 +1,330 @@
+// finished games kept in a sqlite database for the leaderboard
+use std::sync::Mutex;
+use std::time::Instant;
+
+use once_cell::sync::Lazy;
+use rusqlite::{params, Connection, Row};
+use serde::{Deserialize, Serialize};
+use tower_sessions::Session;
+
+use crate::metrics;
+use ui_components::record::Record;
+use ui_components::stats::{self, GameResult, PlayerResult, Summary};
+
+// a game this session started and has not finished, see `start`
+const STARTED_KEY: &str = "game_started";
+
+#[derive(Serialize, Deserialize)]
+struct Started {
+    // unix time
+    at: i64,
+    player: String,
+}
+
+pub static DB: Lazy<Mutex<Connection>> = Lazy::new(|| {
+    let db =
+        Connection::open(&crate::config().stats_db).expect("Unable to open the stats database");
+    create_tables(&db).expect("Unable to create the stats tables");
+    Mutex::new(db)
+});
+
+fn create_tables(db: &Connection) -> rusqlite::Result<()> {
+    db.execute_batch(
+        "CREATE TABLE IF NOT EXISTS results (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            player TEXT NOT NULL,
+            game TEXT NOT NULL,
+            variant TEXT NOT NULL,
+            result TEXT NOT NULL,
+            moves INTEGER NOT NULL,
+            seconds INTEGER,
+            finished TEXT NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS results_player ON results (player);
+        CREATE INDEX IF NOT EXISTS results_game ON results (game, player);",
+    )
+}
+
+fn insert(db: &Connection, result: &GameResult) -> rusqlite::Result<()> {
+    db.execute(
+        "INSERT INTO results (player, game, variant, result, moves, seconds, finished)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            result.player,
+            result.game,
+            result.variant,
+            result.result.name(),
+            result.moves,
+            result.seconds,
+            result.finished,
+        ],
+    )?;
+    Ok(())
+}
+
+// a failed insert is logged, it never fails the game
+pub fn save(result: &GameResult) {
+    if result.player.trim().is_empty() {
+        return;
+    }
+    if let Err(err) = insert(&DB.lock().unwrap(), result) {
+        tracing::error!("failed to record {result:?}: {err}");
+    }
+}
+
+// saves `results` off the async runtime, the database lock can be held by a
+// slow query
+pub async fn save_all(results: Vec<GameResult>) {
+    if results.is_empty() {
+        return;
+    }
+    tokio::task::spawn_blocking(move || results.iter().for_each(save))
+        .await
+        .unwrap();
+}
+
+// whether the database still answers, for /readyz
+pub fn check() -> Result<(), String> {
+    DB.lock()
+        .unwrap()
+        .query_row("SELECT 1", [], |_| Ok(()))
+        .map_err(|err| err.to_string())
+}
+
+pub fn seconds_since(started: Instant) -> Option<i64> {
+    i64::try_from(started.elapsed().as_secs()).ok()
+}
+
+fn read_result(row: &Row) -> rusqlite::Result<GameResult> {
+    let result: String = row.get(3)?;
+    Ok(GameResult {
+        player: row.get(0)?,
+        game: row.get(1)?,
+        variant: row.get(2)?,
+        result: PlayerResult::parse(&result).unwrap_or(PlayerResult::Loss),
+        moves: row.get(4)?,
+        seconds: row.get(5)?,
+        finished: row.get(6)?,
+    })
+}
+
+// the player's results, oldest first
+pub fn results(player: &str) -> Result<Vec<GameResult>, String> {
+    read_results(&DB.lock().unwrap(), player).map_err(|err| err.to_string())
+}
+
+fn read_results(db: &Connection, player: &str) -> rusqlite::Result<Vec<GameResult>> {
+    let mut query = db.prepare_cached(
+        "SELECT player, game, variant, result, moves, seconds, finished FROM results
+        WHERE player = ?1 ORDER BY id",
+    )?;
+    let rows = query.query_map(params![player], read_result)?;
+    rows.collect()
+}
+
+// the LEADERS best players of each game by wins, then win rate, counted by
+// the database
+pub fn leaders() -> Result<Vec<Summary>, String> {
+    read_leaders(&DB.lock().unwrap()).map_err(|err| err.to_string())
+}
+
+fn read_leaders(db: &Connection) -> rusqlite::Result<Vec<Summary>> {
+    let mut query = db.prepare_cached(
+        "SELECT player, game, COUNT(*) AS played, SUM(result = 'win') AS wins,
+            SUM(result = 'loss'), SUM(result = 'draw'), SUM(moves),
+            COALESCE(SUM(seconds), 0), COUNT(seconds)
+        FROM results WHERE game = ?1 GROUP BY player
+        ORDER BY wins DESC, CAST(wins AS REAL) / played DESC, player LIMIT ?2",
+    )?;
+    // streaks depend on the order of the games, they are counted here
+    let mut player_results = db
+        .prepare_cached("SELECT result FROM results WHERE player = ?1 AND game = ?2 ORDER BY id")?;
+    let mut leaders = vec![];
+    for (game, _) in stats::GAMES {
+        let rows = query.query_map(params![game, stats::LEADERS], |row| {
+            Ok(Summary {
+                player: row.get(0)?,
+                game: row.get(1)?,
+                played: row.get(2)?,
+                wins: row.get(3)?,
+                losses: row.get(4)?,
+                draws: row.get(5)?,
+                total_moves: row.get(6)?,
+                total_seconds: row.get(7)?,
+                timed: row.get(8)?,
+                ..Summary::default()
+            })
+        })?;
+        for summary in rows {
+            let mut summary = summary?;
+            let results = player_results
+                .query_map(params![summary.player, game], |row| row.get::<_, String>(0))?
+                .collect::<rusqlite::Result<Vec<String>>>()?;
+            (summary.streak, summary.best_streak) = stats::streaks(
+                results
+                    .iter()
+                    .map(|result| PlayerResult::parse(result).unwrap_or(PlayerResult::Loss)),
+            );
+            leaders.push(summary);
+        }
+    }
+    Ok(leaders)
+}
+
+// remembers that this session started a game of `game` for `player`, the
+// name the result is recorded under whatever later requests say
+pub async fn start(session: &Session, game: &str, player: &str) {
+    metrics::game_started(game, metrics::SERVER);
+    let started = Started {
+        at: chrono::Utc::now().timestamp(),
+        player: player.to_owned(),
+    };
+    session
+        .insert(&format!("{STARTED_KEY}_{game}"), started)
+        .await
+        .unwrap();
+}
+
+// the result of a game this session started with `start`. the start is used
+// up, so a reloaded final page or a replayed final link is not counted again
+async fn take_result(session: &Session, record: &Record) -> Option<GameResult> {
+    let key = format!("{STARTED_KEY}_{}", record.game);
+    if !record.result.is_over() {
+        return None;
+    }
+    let started: Started = session.remove(&key).await.ok().flatten()?;
+    let record = Record {
+        player: started.player,
+        ..record.clone()
+    };
+    let seconds = chrono::Utc::now().timestamp() - started.at;
+    GameResult::from_record(&record, Some(seconds))
+}
+
+// records a finished game this session started with `start`
+pub async fn finish(session: &Session, record: &Record) {
+    if let Some(result) = take_result(session, record).await {
+        metrics::game_finished(&result.game, Some(result.result));
+        save_all(vec![result]).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tower_sessions::MemoryStore;
+    use ui_components::game::{Outcome, Player};
+
+    use super::*;
+
+    fn result(player: &str, game: &str, result: PlayerResult, seconds: Option<i64>) -> GameResult {
+        GameResult {
+            player: player.to_owned(),
+            game: game.to_owned(),
+            variant: "3x3".to_owned(),
+            result,
+            moves: 5,
+            seconds,
+            finished: "2026-10-18 12:00:00".to_owned(),
+        }
+    }
+
+    fn won_game(player: &str) -> Record {
+        let mut record = Record::new("ttt", player, "3x3", Outcome::Win(Player::X));
+        record.moves = ["a1", "b1", "a2", "b2", "a3"].map(str::to_owned).to_vec();
+        record
+    }
+
+    #[test]
+    fn the_database_counts_like_summarize() {
+        use PlayerResult::*;
+        let db = Connection::open_in_memory().unwrap();
+        create_tables(&db).unwrap();
+        let mut results = vec![];
+        for (n, (player, game, outcome)) in [
+            ("bob", "ttt", Win),
+            ("alice", "ttt", Win),
+            ("bob", "ttt", Loss),
+            ("bob", "connect", Draw),
+            ("bob", "ttt", Win),
+            ("bob", "ttt", Win),
+            ("carol", "battleship", Loss),
+            ("alice", "ttt", Draw),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let seconds = (n % 2 == 0).then_some(n as i64);
+            results.push(result(player, game, outcome, seconds));
+        }
+        // more players than the leaderboard shows
+        for n in 0..stats::LEADERS {
+            results.push(result(&format!("player{n:02}"), "ttt", Loss, None));
+        }
+        for result in &results {
+            insert(&db, result).unwrap();
+        }
+
+        // what the leaderboard showed when it summarized every result itself
+        let summaries = stats::summarize(&results);
+        let expected: Vec<Summary> = stats::GAMES
+            .iter()
+            .flat_map(|(game, _)| {
+                let mut rows: Vec<Summary> = summaries
+                    .iter()
+                    .filter(|summary| summary.game == *game)
+                    .cloned()
+                    .collect();
+                rows.sort_by(|a, b| {
+                    b.wins
+                        .cmp(&a.wins)
+                        .then(b.win_rate().total_cmp(&a.win_rate()))
+                        .then(a.player.cmp(&b.player))
+                });
+                rows.truncate(stats::LEADERS);
+                rows
+            })
+            .collect();
+        assert_eq!(read_leaders(&db).unwrap(), expected);
+        assert_eq!(expected[0].player, "bob");
+        assert_eq!((expected[0].streak, expected[0].best_streak), (2, 2));
+
+        let bob = read_results(&db, "bob").unwrap();
+        assert_eq!(bob.len(), 5);
+        assert_eq!(bob[0], results[0]);
+    }
+
+    #[tokio::test]
+    async fn a_game_is_recorded_once() {
+        let session = Session::new(None, Arc::new(MemoryStore::default()), None);
+        start(&session, "ttt", "alice").await;
+        let first = take_result(&session, &won_game("alice")).await.unwrap();
+        assert_eq!(first.player, "alice");
+        assert_eq!(first.result, PlayerResult::Win);
+        // the final page again, under any name
+        for name in ["alice", "bob"] {
+            assert_eq!(take_result(&session, &won_game(name)).await, None);
+        }
+    }
+
+    #[tokio::test]
+    async fn the_name_comes_from_the_start() {
+        let session = Session::new(None, Arc::new(MemoryStore::default()), None);
+        start(&session, "ttt", "alice").await;
+        // a game still being played does not use up the start
+        let mut playing = won_game("bob");
+        playing.result = Outcome::InProgress;
+        assert_eq!(take_result(&session, &playing).await, None);
+        let result = take_result(&session, &won_game("bob")).await.unwrap();
+        assert_eq!(result.player, "alice");
+    }
+
+    #[tokio::test]
+    async fn a_game_that_was_not_started_is_not_recorded() {
+        let session = Session::new(None, Arc::new(MemoryStore::default()), None);
+        assert_eq!(take_result(&session, &won_game("alice")).await, None);
+        start(&session, "connect", "alice").await;
+        assert_eq!(take_result(&session, &won_game("alice")).await, None);
+    }
+}
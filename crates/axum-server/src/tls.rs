@@ -0,0 +1,52 @@
+// https with rustls, enabled by tls_cert and tls_key in config.toml. the plain
+// http port then only redirects to https
+use axum::{
+    extract::Host,
+    http::Uri,
+    response::{IntoResponse, Redirect, Response},
+};
+use axum_serve::tls_rustls::RustlsConfig;
+use tokio::signal::unix::{signal, SignalKind};
+
+use crate::config;
+
+pub async fn load(cert: &str, key: &str) -> Result<RustlsConfig, String> {
+    // ring is already built for the game client, it fails harmlessly when
+    // installed twice
+    let _ = rustls::crypto::ring::default_provider().install_default();
+    RustlsConfig::from_pem_file(cert, key)
+        .await
+        .map_err(|err| format!("Unable to load certificate {cert} and key {key}: {err}"))
+}
+
+// reads the certificate and key again on every SIGHUP, so a renewed
+// certificate is picked up without dropping connections. a pair that does not
+// load leaves the old one in place
+pub async fn reload_on_sighup(tls: RustlsConfig, cert: String, key: String) {
+    let mut hangups = signal(SignalKind::hangup()).expect("Unable to listen for SIGHUP");
+    while hangups.recv().await.is_some() {
+        match tls.reload_from_pem_file(&cert, &key).await {
+            Ok(()) => tracing::info!("reloaded certificate {cert}"),
+            Err(err) => tracing::error!("failed to reload certificate {cert}: {err}"),
+        }
+    }
+}
+
+pub fn new_redirect_router() -> axum::Router {
+    axum::Router::new().fallback(redirect_handler)
+}
+
+// the same host and path on the https port, 308 so forms are posted again
+async fn redirect_handler(Host(host): Host, uri: Uri) -> Response {
+    // "example.com:80" and "[::1]:80" lose their port, "[::1]" keeps its brackets
+    let name = match host.rsplit_once(':') {
+        Some((name, port)) if !port.contains(']') => name,
+        _ => host.as_str(),
+    };
+    let port = match config().https_port {
+        443 => String::new(),
+        port => format!(":{port}"),
+    };
+    let path = uri.path_and_query().map_or("/", |path| path.as_str());
+    Redirect::permanent(&format!("https://{name}{port}{path}")).into_response()
+}
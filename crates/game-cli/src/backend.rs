@@ -0,0 +1,93 @@
+// where the games are played: a running server through its json api, or the
+// same engine in this process
+use serde::Deserialize;
+use ui_components::api::{ApiGame, NewGame};
+use ui_components::game::Outcome;
+
+// the game as the json api describes it, see `ui_components::api::GameState`
+#[derive(Debug, Clone, Deserialize)]
+pub struct View {
+    pub game: String,
+    pub variant: String,
+    pub board: Vec<Vec<String>>,
+    pub player_board: Option<Vec<Vec<String>>>,
+    pub legal_moves: Vec<String>,
+    pub outcome: Outcome,
+    pub moves_left: Option<i32>,
+    #[serde(default)]
+    pub server_move: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Created {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct ErrorBody {
+    error: String,
+}
+
+pub enum Backend {
+    Offline(ApiGame),
+    // `url` is the game's own url, such as http://localhost/api/v1/ttt/1f2e
+    Server { url: String },
+}
+
+impl Backend {
+    pub fn offline(game: &str, options: NewGame) -> Result<(Self, View), String> {
+        let api_game = ApiGame::new(game, options)?;
+        let view = offline_view(&api_game, None)?;
+        Ok((Backend::Offline(api_game), view))
+    }
+
+    // `server` is the server's root, such as http://localhost
+    pub fn connect(server: &str, game: &str, options: NewGame) -> Result<(Self, View), String> {
+        let url = format!("{}/api/v1/{game}", server.trim_end_matches('/'));
+        let body = serde_json::to_value(options).map_err(|err| err.to_string())?;
+        let response = ureq::post(&url).send_json(body);
+        let json: serde_json::Value = read_response(response)?;
+        let created = Created::deserialize(&json).map_err(|err| err.to_string())?;
+        let view = View::deserialize(&json).map_err(|err| err.to_string())?;
+        let url = format!("{url}/{}", created.id);
+        Ok((Backend::Server { url }, view))
+    }
+
+    // plays `mv` and the opponent's reply
+    pub fn play(&mut self, mv: &str) -> Result<View, String> {
+        match self {
+            Backend::Offline(api_game) => {
+                let server_move = api_game.play(mv)?;
+                offline_view(api_game, server_move)
+            }
+            Backend::Server { url } => {
+                let response = ureq::post(&format!("{url}/moves"))
+                    .send_json(serde_json::json!({ "move": mv }));
+                read_response(response)
+            }
+        }
+    }
+}
+
+// offline games go through the same json as the server's so both are drawn
+// alike
+fn offline_view(api_game: &ApiGame, server_move: Option<String>) -> Result<View, String> {
+    let state = serde_json::to_value(api_game.state()).map_err(|err| err.to_string())?;
+    let mut view = View::deserialize(state).map_err(|err| err.to_string())?;
+    view.server_move = server_move;
+    Ok(view)
+}
+
+fn read_response<T: serde::de::DeserializeOwned>(
+    response: Result<ureq::Response, ureq::Error>,
+) -> Result<T, String> {
+    match response {
+        Ok(response) => response.into_json().map_err(|err| err.to_string()),
+        // the api explains its errors in the body
+        Err(ureq::Error::Status(status, response)) => Err(match response.into_json() {
+            Ok(ErrorBody { error }) => error,
+            Err(_) => format!("Server answered {status}"),
+        }),
+        Err(err) => Err(err.to_string()),
+    }
+}
@@ -0,0 +1,96 @@
+// boards drawn with ansi colours
+use ui_components::game::{Outcome, Player};
+
+use crate::backend::View;
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const RED: &str = "\x1b[31m";
+const YELLOW: &str = "\x1b[33m";
+const BLUE: &str = "\x1b[34m";
+const WHITE: &str = "\x1b[37m";
+
+pub struct Painter {
+    pub color: bool,
+}
+
+impl Painter {
+    fn paint(&self, code: &str, text: &str) -> String {
+        if self.color {
+            format!("{code}{text}{RESET}")
+        } else {
+            text.to_owned()
+        }
+    }
+
+    pub fn error(&self, text: &str) -> String {
+        self.paint(RED, text)
+    }
+
+    pub fn title(&self, text: &str) -> String {
+        self.paint(BOLD, text)
+    }
+
+    // battleship shows "X" hit, "O" miss and "#" ship, the other games the
+    // player's and the server's pieces
+    fn cell(&self, game: &str, symbol: &str) -> String {
+        match (game, symbol) {
+            ("battleship", "X") => self.paint(RED, "X"),
+            ("battleship", "O") => self.paint(BLUE, "o"),
+            ("battleship", "#") => self.paint(WHITE, "#"),
+            ("connect", "O") => self.paint(YELLOW, "O"),
+            (_, "X") => self.paint(RED, "X"),
+            (_, "O") => self.paint(BLUE, "O"),
+            _ => self.paint(DIM, "."),
+        }
+    }
+
+    // a grid with column numbers on top, and row numbers on the left unless
+    // moves are plain columns
+    pub fn grid(&self, game: &str, rows: &[Vec<String>]) -> String {
+        let cols = rows.first().map_or(0, Vec::len);
+        let numbered_rows = game != "connect";
+        let margin = if numbered_rows { "   " } else { "" };
+        let mut out = String::from(margin);
+        for col in 0..cols {
+            out += &self.paint(DIM, &format!("{col:>3}"));
+        }
+        out.push('\n');
+        for (row, cells) in rows.iter().enumerate() {
+            if numbered_rows {
+                out += &self.paint(DIM, &format!("{row:>3}"));
+            }
+            for symbol in cells {
+                out += &format!("  {}", self.cell(game, symbol));
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    pub fn view(&self, view: &View) -> String {
+        let mut out = String::new();
+        if let Some(player_board) = &view.player_board {
+            out += &self.title("Your fleet\n");
+            out += &self.grid(&view.game, player_board);
+            out += &self.title("\nEnemy waters\n");
+        }
+        out += &self.grid(&view.game, &view.board);
+        if let Some(moves_left) = view.moves_left {
+            out += &format!("Shots left: {moves_left}\n");
+        }
+        out
+    }
+
+    pub fn outcome(&self, game: &str, outcome: Outcome) -> String {
+        match outcome {
+            Outcome::Win(Player::X) => self.paint(BOLD, "You win!"),
+            // a one-sided battleship game is lost by running out of shots
+            Outcome::Win(Player::O) if game == "battleship" => self.paint(RED, "You lose."),
+            Outcome::Win(Player::O) => self.paint(RED, "The server wins."),
+            Outcome::Draw => self.paint(YELLOW, "Draw."),
+            Outcome::InProgress => String::new(),
+        }
+    }
+}
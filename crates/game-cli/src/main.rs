@@ -0,0 +1,161 @@
+use std::io::{self, BufRead, IsTerminal, Write};
+
+use clap::{Parser, ValueEnum};
+use ui_components::api::NewGame;
+use ui_components::ttt::Difficulty;
+
+mod backend;
+mod draw;
+
+use backend::{Backend, View};
+use draw::Painter;
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum GameName {
+    Ttt,
+    Connect,
+    Battleship,
+}
+
+impl GameName {
+    // the name used by the json api
+    fn api_name(self) -> &'static str {
+        match self {
+            GameName::Ttt => "ttt",
+            GameName::Connect => "connect",
+            GameName::Battleship => "battleship",
+        }
+    }
+
+    fn prompt(self) -> &'static str {
+        match self {
+            GameName::Connect => "Column",
+            GameName::Ttt | GameName::Battleship => "Row,col",
+        }
+    }
+}
+
+/// Plays tic-tac-toe, Connect-4 and Battleship in the terminal
+#[derive(Debug, Parser)]
+struct Args {
+    game: GameName,
+    /// Your name, games with a name count towards the server's leaderboard
+    #[arg(long)]
+    name: Option<String>,
+    /// Root of the server to play against
+    #[arg(long, default_value = "http://localhost")]
+    server: String,
+    /// Play against the engine in this process instead of a server
+    #[arg(long)]
+    offline: bool,
+    /// Board size such as "3x3", "gomoku" or "6x7"
+    #[arg(long)]
+    variant: Option<String>,
+    /// Tic-tac-toe only: easy, medium or perfect
+    #[arg(long, value_parser = parse_difficulty)]
+    difficulty: Option<Difficulty>,
+    /// Connect-4 only, how far ahead the server looks
+    #[arg(long)]
+    depth: Option<u32>,
+    /// Battleship only: one-sided or two-sided
+    #[arg(long)]
+    mode: Option<String>,
+    /// Battleship only, the same seed places the same fleet
+    #[arg(long)]
+    seed: Option<u64>,
+    /// Battleship only, ships may not touch
+    #[arg(long)]
+    no_touch: bool,
+    /// Two-sided battleship only, your ships as "row,col,h|v;..."
+    #[arg(long)]
+    fleet: Option<String>,
+    /// Draw without colours, also when NO_COLOR is set or output is not a terminal
+    #[arg(long)]
+    no_color: bool,
+}
+
+fn parse_difficulty(name: &str) -> Result<Difficulty, String> {
+    serde_json::from_value(serde_json::Value::String(name.to_owned()))
+        .map_err(|_| "expected easy, medium or perfect".to_owned())
+}
+
+fn main() {
+    let args = Args::parse();
+    let painter = Painter {
+        color: !args.no_color
+            && std::env::var_os("NO_COLOR").is_none()
+            && io::stdout().is_terminal(),
+    };
+    if let Err(err) = run(&args, &painter) {
+        eprintln!("{}", painter.error(&err));
+        std::process::exit(1);
+    }
+}
+
+fn run(args: &Args, painter: &Painter) -> Result<(), String> {
+    let options = NewGame {
+        name: args.name.clone(),
+        variant: args.variant.clone(),
+        difficulty: args.difficulty.unwrap_or_default(),
+        depth: args.depth,
+        mode: args.mode.clone(),
+        seed: args.seed,
+        no_touch: args.no_touch,
+        fleet: args.fleet.clone(),
+    };
+    let game = args.game.api_name();
+    let (mut backend, mut view) = if args.offline {
+        Backend::offline(game, options)?
+    } else {
+        Backend::connect(&args.server, game, options)?
+    };
+
+    println!("{}", painter.title(&format!("{game} {}", view.variant)));
+    println!("Type a move, or q to quit.\n");
+    print!("{}", painter.view(&view));
+
+    let mut lines = io::stdin().lock().lines();
+    while !view.outcome.is_over() {
+        print!("{} [{}]: ", args.game.prompt(), hint(&view));
+        io::stdout().flush().map_err(|err| err.to_string())?;
+        let Some(line) = lines.next() else {
+            println!();
+            return Ok(());
+        };
+        let line = line.map_err(|err| err.to_string())?;
+        // "2 3" is read as "2,3"
+        let mv = line
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join(",");
+        match mv.as_str() {
+            "" => continue,
+            "q" | "quit" => return Ok(()),
+            _ => {}
+        }
+        match backend.play(&mv) {
+            Ok(next) => {
+                view = next;
+                println!();
+                if let Some(server_move) = &view.server_move {
+                    println!("Server played {server_move}");
+                }
+                print!("{}", painter.view(&view));
+            }
+            Err(err) => println!("{}", painter.error(&err)),
+        }
+    }
+    println!("{}", painter.outcome(&view.game, view.outcome));
+    Ok(())
+}
+
+// the first few legal moves
+fn hint(view: &View) -> String {
+    const SHOWN: usize = 5;
+    let mut hint = view.legal_moves[..view.legal_moves.len().min(SHOWN)].join(" ");
+    if view.legal_moves.len() > SHOWN {
+        hint += " ...";
+    }
+    hint
+}
@@ -0,0 +1,285 @@
+// games played through the json api instead of the html pages
+//
+// the api plays the same rules and the same server replies as the pages. a
+// move is "row,col" for tic-tac-toe and battleship and a column counted from
+// 0 for connect4, the same way the legal moves are listed.
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::battleship::{self, Coordinate, FleetOptions, Hit, Miss, Mode, Ship, Untried};
+use crate::connect;
+use crate::connect_engine::MAX_DEPTH;
+use crate::game::{Game, Outcome};
+use crate::record::Record;
+use crate::ttt::{self, Difficulty};
+
+// the body of a request that starts a game, every field is optional
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct NewGame {
+    // the player's name, games with a name count towards the leaderboard
+    pub name: Option<String>,
+    // a board size such as "3x3", "gomoku" or "6x7"
+    pub variant: Option<String>,
+    // tic-tac-toe only
+    pub difficulty: Difficulty,
+    // connect4 only, how far ahead the server looks
+    pub depth: Option<u32>,
+    // battleship only, "one-sided" or "two-sided"
+    pub mode: Option<String>,
+    // battleship only, see the battleship page
+    pub seed: Option<u64>,
+    pub no_touch: bool,
+    // two-sided battleship only, the player's ships as "row,col,h|v;..."
+    pub fleet: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum ApiGame {
+    Ttt {
+        board: ttt::Board,
+        difficulty: Difficulty,
+    },
+    Connect {
+        board: connect::Board,
+        depth: u32,
+    },
+    Battleship {
+        board: battleship::Board,
+        // the player's fleet in a two-sided game
+        player_board: Option<battleship::Board>,
+    },
+}
+
+// everything a client needs to show the game and pick its next move
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct GameState {
+    pub game: &'static str,
+    pub variant: String,
+    // rows of "X", "O" or "" for tic-tac-toe and connect4. for battleship the
+    // waters the player fires at, "X" hit, "O" miss and "" untried, with the
+    // ships shown as "#" once the game is over
+    pub board: Vec<Vec<&'static str>>,
+    // the player's own fleet in a two-sided battleship game, ships included
+    pub player_board: Option<Vec<Vec<&'static str>>>,
+    pub legal_moves: Vec<String>,
+    // X is the player, O the server
+    pub outcome: Outcome,
+    // one-sided battleship only
+    pub moves_left: Option<i32>,
+}
+
+fn waters(board: &battleship::Board, reveal: bool) -> Vec<Vec<&'static str>> {
+    board
+        .tiles
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|tile| match tile {
+                    Hit => "X",
+                    Miss => "O",
+                    Ship if reveal => "#",
+                    Untried | Ship => "",
+                })
+                .collect()
+        })
+        .collect()
+}
+
+impl ApiGame {
+    // `game` is "ttt", "connect" or "battleship"
+    pub fn new(game: &str, options: NewGame) -> Result<Self, String> {
+        let variant = options.variant.as_deref();
+        match game {
+            "ttt" => Ok(ApiGame::Ttt {
+                board: ttt::Board::with_variant(match variant {
+                    None => ttt::Variant::TIC_TAC_TOE,
+                    Some(name) => {
+                        ttt::variant_from_name(name).ok_or(format!("Unknown variant \"{name}\""))?
+                    }
+                }),
+                difficulty: options.difficulty,
+            }),
+            "connect" => Ok(ApiGame::Connect {
+                board: connect::Board::with_variant(match variant {
+                    None => connect::Variant::CONNECT_FOUR,
+                    Some(name) => connect::variant_from_name(name)
+                        .ok_or(format!("Unknown variant \"{name}\""))?,
+                }),
+                depth: options
+                    .depth
+                    .unwrap_or(connect::DEFAULT_DEPTH)
+                    .clamp(1, MAX_DEPTH),
+            }),
+            "battleship" => {
+                let mode = match options.mode.as_deref() {
+                    None => Mode::default(),
+                    Some(mode) => Mode::parse(mode).ok_or(format!("Unknown mode \"{mode}\""))?,
+                };
+                let fleet_options = FleetOptions {
+                    seed: options.seed,
+                    no_touch: options.no_touch,
+                };
+                if mode == Mode::OneSided {
+                    return Ok(ApiGame::Battleship {
+                        board: battleship::Board::one_sided(&fleet_options)?,
+                        player_board: None,
+                    });
+                }
+                let board = battleship::Board::two_sided(&fleet_options)?;
+                let player_board = match options.fleet.as_deref().map(str::trim) {
+                    None | Some("") => battleship::Board::player_fleet(&board, &fleet_options)?,
+                    Some(fleet) => battleship::Board::from_fleet(
+                        battleship::place_fleet(
+                            battleship::ROWS,
+                            battleship::COLS,
+                            &battleship::SHIPS,
+                            fleet,
+                            options.no_touch,
+                        )?,
+                        None,
+                    ),
+                };
+                Ok(ApiGame::Battleship {
+                    board,
+                    player_board: Some(player_board),
+                })
+            }
+            game => Err(format!("Unknown game \"{game}\"")),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ApiGame::Ttt { .. } => "ttt",
+            ApiGame::Connect { .. } => "connect",
+            ApiGame::Battleship { .. } => "battleship",
+        }
+    }
+
+    pub fn outcome(&self) -> Outcome {
+        match self {
+            ApiGame::Ttt { board, .. } => board.outcome(),
+            ApiGame::Connect { board, .. } => board.outcome(),
+            ApiGame::Battleship {
+                board,
+                player_board: Some(player_board),
+            } => battleship::two_sided_outcome(board, player_board),
+            ApiGame::Battleship { board, .. } => board.outcome(),
+        }
+    }
+
+    pub fn record(&self, name: &str) -> Record {
+        match self {
+            ApiGame::Ttt { board, .. } => board.record(name),
+            ApiGame::Connect { board, .. } => board.record(name),
+            ApiGame::Battleship {
+                board,
+                player_board: Some(player_board),
+            } => battleship::two_sided_record(name, board, player_board),
+            ApiGame::Battleship { board, .. } => battleship::one_sided_record(name, board),
+        }
+    }
+
+    // plays the player's move and then the server's, returns the server's
+    // move in the same notation when it made one
+    pub fn play(&mut self, mv: &str) -> Result<Option<String>, String> {
+        if self.outcome().is_over() {
+            return Err("Game is over".to_string());
+        }
+        match self {
+            ApiGame::Ttt { board, difficulty } => {
+                let variant = board.grid().variant();
+                let target = Coordinate::parse(mv, variant.rows, variant.cols)?;
+                board.apply_move(target.into())?;
+                Ok(board
+                    .reply(*difficulty)
+                    .map(|(row, col)| format!("{row},{col}")))
+            }
+            ApiGame::Connect { board, depth } => {
+                let col = mv
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("\"{mv}\" is not a column"))?;
+                board.apply_move(col)?;
+                Ok(board.reply(*depth).map(|col| col.to_string()))
+            }
+            ApiGame::Battleship {
+                board,
+                player_board,
+            } => {
+                let target = Coordinate::parse(mv, battleship::ROWS, battleship::COLS)?;
+                board.apply_move(target.into())?;
+                // the server fires back unless the player just won
+                match player_board {
+                    Some(player_board) if !board.outcome().is_over() => {
+                        Ok(battleship::server_fire(player_board)
+                            .map(|(row, col)| format!("{row},{col}")))
+                    }
+                    _ => Ok(None),
+                }
+            }
+        }
+    }
+
+    pub fn state(&self) -> GameState {
+        let outcome = self.outcome();
+        match self {
+            ApiGame::Ttt { board, .. } => GameState {
+                game: self.name(),
+                variant: board.grid().variant().to_string(),
+                board: board.grid().symbols(),
+                player_board: None,
+                legal_moves: board
+                    .legal_moves()
+                    .into_iter()
+                    .map(|(row, col)| format!("{row},{col}"))
+                    .collect(),
+                outcome,
+                moves_left: None,
+            },
+            ApiGame::Connect { board, .. } => GameState {
+                game: self.name(),
+                variant: board.grid().variant().to_string(),
+                board: board.grid().symbols(),
+                player_board: None,
+                legal_moves: board
+                    .legal_moves()
+                    .into_iter()
+                    .map(|col| col.to_string())
+                    .collect(),
+                outcome,
+                moves_left: None,
+            },
+            ApiGame::Battleship {
+                board,
+                player_board,
+            } => {
+                let legal_moves = if outcome.is_over() {
+                    vec![]
+                } else {
+                    board
+                        .legal_moves()
+                        .into_iter()
+                        .map(|(row, col)| format!("{row},{col}"))
+                        .collect()
+                };
+                let mode = match player_board {
+                    Some(_) => Mode::TwoSided,
+                    None => Mode::OneSided,
+                };
+                GameState {
+                    game: self.name(),
+                    variant: mode.name().to_owned(),
+                    board: waters(board, outcome.is_over()),
+                    player_board: player_board
+                        .as_ref()
+                        .map(|player_board| waters(player_board, true)),
+                    legal_moves,
+                    outcome,
+                    moves_left: board.moves_left,
+                }
+            }
+        }
+    }
+}
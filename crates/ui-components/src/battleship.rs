@@ -1,9 +1,15 @@
 // code for battleship game
 
 use chrono::Utc;
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
 use serde::{Deserialize, Serialize};
 
+use crate::game::{Game, Outcome, Player};
+use crate::html;
+use crate::record::{self, Diagram, Record, Replay};
+
 pub const BATTLESHIP_GET_PAGE: &str = r#"
 <!DOCTYPE html>
 <html>
@@ -14,13 +20,24 @@ pub const BATTLESHIP_GET_PAGE: &str = r#"
     <form action="battleship.php" method="post">
         <label for="name">Name:</label>
         <input type="text" id="name" name="name"/>
+        <label for="mode">Mode:</label>
+        <select id="mode" name="mode">
+            <option value="one-sided" selected>One-sided</option>
+            <option value="two-sided">Two-sided</option>
+        </select>
+        <label for="fleet">Your fleet (two-sided only):</label>
+        <input type="text" id="fleet" name="fleet" placeholder="0,0,h;2,1,v;4,0,h"/>
+        <label for="seed">Seed (optional):</label>
+        <input type="number" id="seed" name="seed" min="0"/>
+        <label for="no_touch">Ships may not touch:</label>
+        <input type="checkbox" id="no_touch" name="no_touch"/>
         <input type="submit" value="Submit"/>
     </form>
 </body>
 </html>
 "#;
 
-#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Tile {
     Hit,
     Miss,
@@ -29,65 +46,675 @@ pub enum Tile {
 }
 pub use Tile::*;
 
-pub fn create_battleship_game(rows: usize, cols: usize, ships: &[usize]) -> Vec<Vec<Tile>> {
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Mode {
+    // the player hunts the server's fleet with a limited number of moves
+    #[default]
+    OneSided,
+    // both sides have a fleet and take turns firing at each other
+    TwoSided,
+}
+
+impl Mode {
+    pub fn parse(mode: &str) -> Option<Mode> {
+        match mode {
+            "one-sided" => Some(Mode::OneSided),
+            "two-sided" => Some(Mode::TwoSided),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Mode::OneSided => "one-sided",
+            Mode::TwoSided => "two-sided",
+        }
+    }
+}
+
+// a cell on the board as submitted in a "row,col" move
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Coordinate {
+    pub row: usize,
+    pub col: usize,
+}
+
+impl Coordinate {
+    // both parts must be numbers inside a `rows` x `cols` board
+    pub fn parse(value: &str, rows: usize, cols: usize) -> Result<Self, String> {
+        let (row, col) = value
+            .split_once(',')
+            .ok_or(format!("\"{value}\" is not of the form row,col"))?;
+        let (Ok(row), Ok(col)) = (row.trim().parse(), col.trim().parse()) else {
+            return Err(format!("\"{value}\" is not a pair of numbers"));
+        };
+        if row >= rows || col >= cols {
+            return Err(format!("{row},{col} is outside the {rows}x{cols} board"));
+        }
+        Ok(Coordinate { row, col })
+    }
+}
+
+impl From<Coordinate> for (usize, usize) {
+    fn from(coordinate: Coordinate) -> Self {
+        (coordinate.row, coordinate.col)
+    }
+}
+
+// one ship of a fleet and the cells it covers
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlacedShip {
+    pub length: usize,
+    pub cells: Vec<(usize, usize)>,
+}
+
+impl PlacedShip {
+    // ships are named by their size, e.g. "3x1"
+    pub fn name(&self) -> String {
+        format!("{}x1", self.length)
+    }
+
+    pub fn is_sunk(&self, board: &[Vec<Tile>]) -> bool {
+        self.cells.iter().all(|&(i, j)| board[i][j] == Hit)
+    }
+}
+
+// the tiles of a fleet together with its ships
+pub type Fleet = (Vec<Vec<Tile>>, Vec<PlacedShip>);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Orientation {
+    Horizontal,
+    Vertical,
+}
+
+// cells covered by a ship, None if it does not fit on the board
+fn ship_cells(
+    rows: usize,
+    cols: usize,
+    (r, c): (usize, usize),
+    length: usize,
+    orientation: Orientation,
+) -> Option<Vec<(usize, usize)>> {
+    match orientation {
+        Orientation::Horizontal if r < rows && c + length <= cols => {
+            Some((c..c + length).map(|j| (r, j)).collect())
+        }
+        Orientation::Vertical if c < cols && r + length <= rows => {
+            Some((r..r + length).map(|i| (i, c)).collect())
+        }
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FleetOptions {
+    // fixed seed for a reproducible fleet, a random one is picked otherwise
+    pub seed: Option<u64>,
+    // ships may not touch each other, not even diagonally
+    pub no_touch: bool,
+}
+
+// true if a ship may occupy all of `cells`
+fn cells_free(board: &[Vec<Tile>], cells: &[(usize, usize)], no_touch: bool) -> bool {
+    cells.iter().all(|&(i, j)| {
+        if !no_touch {
+            return board[i][j] != Ship;
+        }
+        let rows = i.saturating_sub(1)..=(i + 1).min(board.len() - 1);
+        rows.flat_map(|r| {
+            let cols = j.saturating_sub(1)..=(j + 1).min(board[r].len() - 1);
+            cols.map(move |c| (r, c))
+        })
+        .all(|(r, c)| board[r][c] != Ship)
+    })
+}
+
+// parses a fleet of the form "row,col,h;row,col,v;..." with one entry per ship
+// length in `ships`, in the same order
+pub fn place_fleet(
+    rows: usize,
+    cols: usize,
+    ships: &[usize],
+    fleet: &str,
+    no_touch: bool,
+) -> Result<Fleet, String> {
+    let entries: Vec<&str> = fleet.split(';').map(str::trim).collect();
+    if entries.len() != ships.len() {
+        return Err(format!(
+            "Expected {} ships but got {}",
+            ships.len(),
+            entries.len()
+        ));
+    }
     let mut board = vec![vec![Untried; cols]; rows];
-    let mut rng = rand::thread_rng();
-    const MAX_ATTEMPTS: usize = 100;
-    for length in ships {
-        match rng.gen_range(0..2) {
-            0 => {
-                // horizontal
-                'place: for _ in 0..MAX_ATTEMPTS {
-                    let r = rng.gen_range(0..rows);
-                    let c = rng.gen_range(0..(cols - length));
-                    for j in c..(c + length) {
-                        if board[r][j] != Untried {
-                            continue 'place;
-                        }
+    let mut placed = vec![];
+    for (entry, &length) in entries.iter().zip(ships) {
+        let parts: Vec<&str> = entry.split(',').map(str::trim).collect();
+        let (r, c, orientation) = match parts.as_slice() {
+            [r, c, o] => (r.parse::<usize>(), c.parse::<usize>(), o.to_lowercase()),
+            _ => return Err(format!("Invalid ship \"{entry}\"")),
+        };
+        let (Ok(r), Ok(c)) = (r, c) else {
+            return Err(format!("Invalid position in \"{entry}\""));
+        };
+        let orientation = match orientation.as_str() {
+            "h" => Orientation::Horizontal,
+            "v" => Orientation::Vertical,
+            _ => return Err(format!("Invalid orientation in \"{entry}\"")),
+        };
+        let cells = ship_cells(rows, cols, (r, c), length, orientation)
+            .ok_or(format!("Ship \"{entry}\" does not fit on the board"))?;
+        if !cells_free(&board, &cells, no_touch) {
+            return Err(if no_touch {
+                format!("Ship \"{entry}\" overlaps or touches another ship")
+            } else {
+                format!("Ship \"{entry}\" overlaps another ship")
+            });
+        }
+        for &(i, j) in &cells {
+            board[i][j] = Ship;
+        }
+        placed.push(PlacedShip { length, cells });
+    }
+    Ok((board, placed))
+}
+
+// applies a shot to the board, returns false if the cell was already tried
+pub fn fire(board: &mut [Vec<Tile>], (i, j): (usize, usize)) -> bool {
+    match board[i][j] {
+        Ship => board[i][j] = Hit,
+        Untried => board[i][j] = Miss,
+        _ => return false,
+    }
+    true
+}
+
+pub fn count_hits(board: &[Vec<Tile>]) -> usize {
+    board
+        .iter()
+        .map(|row| row.iter().filter(|&&tile| tile == Hit).count())
+        .sum()
+}
+
+// picks the server's next shot at the player's fleet
+//
+// every placement of every ship that avoids known misses adds weight to the
+// cells it covers. while there are hits on the board only placements through
+// those hits count (target mode), otherwise all placements do (hunt mode). the
+// untried cell with the highest density is chosen, ties broken at random.
+pub fn choose_target(board: &[Vec<Tile>], ships: &[usize]) -> Option<(usize, usize)> {
+    let targeting = count_hits(board) > 0;
+    let mut density = placement_density(board, ships, targeting);
+    if targeting && density.iter().flatten().all(|&d| d == 0) {
+        // every hit is already explained by a sunk ship, go back to hunting
+        density = placement_density(board, ships, false);
+    }
+
+    let mut candidates = vec![];
+    let mut best = 0;
+    for (i, row) in board.iter().enumerate() {
+        for (j, tile) in row.iter().enumerate() {
+            if matches!(tile, Hit | Miss) || density[i][j] < best {
+                continue;
+            }
+            if density[i][j] > best {
+                best = density[i][j];
+                candidates.clear();
+            }
+            candidates.push((i, j));
+        }
+    }
+    candidates.choose(&mut rand::thread_rng()).copied()
+}
+
+fn placement_density(board: &[Vec<Tile>], ships: &[usize], targeting: bool) -> Vec<Vec<u32>> {
+    let rows = board.len();
+    let cols = board.first().map_or(0, Vec::len);
+    let mut density = vec![vec![0u32; cols]; rows];
+    for &length in ships {
+        for r in 0..rows {
+            for c in 0..cols {
+                for orientation in [Orientation::Horizontal, Orientation::Vertical] {
+                    let Some(cells) = ship_cells(rows, cols, (r, c), length, orientation) else {
+                        continue;
+                    };
+                    if cells.iter().any(|&(i, j)| board[i][j] == Miss) {
+                        continue;
                     }
-                    for j in c..(c + length) {
-                        board[r][j] = Ship;
+                    let hits = cells.iter().filter(|&&(i, j)| board[i][j] == Hit).count();
+                    if targeting && (hits == 0 || hits == length) {
+                        continue;
                     }
-                    break;
-                }
-            }
-            1 => {
-                // vertical
-                'place: for _ in 0..MAX_ATTEMPTS {
-                    let r = rng.gen_range(0..(rows - length));
-                    let c = rng.gen_range(0..cols);
-                    for row in &board[r..r + length] {
-                        if row[c] != Untried {
-                            continue 'place;
+                    // the server must not see where the player's ships are,
+                    // so `Ship` counts as an untried cell here
+                    for (i, j) in cells {
+                        if board[i][j] != Hit {
+                            density[i][j] += 1 + hits as u32;
                         }
                     }
-                    for row in &mut board[r..r + length] {
-                        row[c] = Ship;
+                }
+            }
+        }
+    }
+    density
+}
+
+// places every ship at random, backtracking over earlier ships whenever one
+// no longer fits, so this only fails if no arrangement exists at all
+pub fn create_battleship_game(
+    rows: usize,
+    cols: usize,
+    ships: &[usize],
+    seed: u64,
+    no_touch: bool,
+) -> Result<Fleet, String> {
+    let mut board = vec![vec![Untried; cols]; rows];
+    let mut placed = vec![];
+    let mut rng = StdRng::seed_from_u64(seed);
+    // longest ships first, they are the hardest to fit
+    let mut lengths = ships.to_vec();
+    lengths.sort_unstable_by(|a, b| b.cmp(a));
+    if place_ships(&mut board, &mut placed, &lengths, no_touch, &mut rng) {
+        placed.sort_by_key(|ship| ship.length);
+        Ok((board, placed))
+    } else {
+        Err(format!(
+            "Cannot fit ships {ships:?} on a {rows}x{cols} board"
+        ))
+    }
+}
+
+fn place_ships(
+    board: &mut [Vec<Tile>],
+    placed: &mut Vec<PlacedShip>,
+    ships: &[usize],
+    no_touch: bool,
+    rng: &mut StdRng,
+) -> bool {
+    let Some((&length, rest)) = ships.split_first() else {
+        return true;
+    };
+    let (rows, cols) = (board.len(), board.first().map_or(0, Vec::len));
+    let mut placements = vec![];
+    for r in 0..rows {
+        for c in 0..cols {
+            for orientation in [Orientation::Horizontal, Orientation::Vertical] {
+                if let Some(cells) = ship_cells(rows, cols, (r, c), length, orientation) {
+                    if cells_free(board, &cells, no_touch) {
+                        placements.push(cells);
                     }
-                    break;
                 }
             }
-            _ => unreachable!(),
         }
     }
-    board
+    placements.shuffle(rng);
+    for cells in placements {
+        for &(i, j) in &cells {
+            board[i][j] = Ship;
+        }
+        placed.push(PlacedShip { length, cells });
+        if place_ships(board, placed, rest, no_touch, rng) {
+            return true;
+        }
+        let cells = placed.pop().map_or(vec![], |ship| ship.cells);
+        for (i, j) in cells {
+            board[i][j] = Untried;
+        }
+    }
+    false
 }
 
 pub const ROWS: usize = 5;
 pub const COLS: usize = 7;
 pub const SHIPS: [usize; 3] = [2, 3, 4];
 
-pub fn make_board_page(name: String, board: Vec<Vec<Tile>>, moves_left: i32) -> String {
-    let time_formatted = Utc::now().format("%Y-%m-%d");
+pub fn initial_moves() -> i32 {
+    ((COLS as f64) * (ROWS as f64) * 0.60).ceil() as i32
+}
+
+// the waters one side is firing at, the shooter always plays as X
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Board {
+    pub tiles: Vec<Vec<Tile>>,
+    // None when the shooter has unlimited moves (two-sided games)
+    pub moves_left: Option<i32>,
+    // seed the fleet was placed with, None for a fleet placed by hand
+    pub seed: Option<u64>,
+    // cells fired at so far, oldest first
+    #[serde(default)]
+    pub shots: Vec<(usize, usize)>,
+    // the ships hidden in `tiles`, empty when they are not known
+    #[serde(default)]
+    pub ships: Vec<PlacedShip>,
+}
+
+impl Board {
+    fn random(options: &FleetOptions, moves_left: Option<i32>) -> Result<Self, String> {
+        let seed = options.seed.unwrap_or_else(|| rand::thread_rng().gen());
+        let fleet = create_battleship_game(ROWS, COLS, &SHIPS, seed, options.no_touch)?;
+        Ok(Board {
+            seed: Some(seed),
+            ..Board::from_fleet(fleet, moves_left)
+        })
+    }
+
+    pub fn one_sided(options: &FleetOptions) -> Result<Self, String> {
+        Board::random(options, Some(initial_moves()))
+    }
+
+    pub fn two_sided(options: &FleetOptions) -> Result<Self, String> {
+        Board::random(options, None)
+    }
+
+    // the player's fleet in a two-sided game they did not place by hand,
+    // derived from the server's seed so the whole game is reproducible
+    pub fn player_fleet(board: &Board, options: &FleetOptions) -> Result<Self, String> {
+        Board::two_sided(&FleetOptions {
+            seed: board.seed.map(|seed| seed.wrapping_add(1)),
+            ..*options
+        })
+    }
+
+    pub fn from_tiles(tiles: Vec<Vec<Tile>>, moves_left: Option<i32>) -> Self {
+        Board::from_fleet((tiles, vec![]), moves_left)
+    }
+
+    pub fn from_fleet((tiles, ships): Fleet, moves_left: Option<i32>) -> Self {
+        Board {
+            tiles,
+            moves_left,
+            seed: None,
+            shots: vec![],
+            ships,
+        }
+    }
+
+    pub fn ship_at(&self, cell: (usize, usize)) -> Option<&PlacedShip> {
+        self.ships.iter().find(|ship| ship.cells.contains(&cell))
+    }
+
+    // the ship the last shot sank, if it sank one
+    pub fn last_sunk(&self) -> Option<&PlacedShip> {
+        let ship = self.ship_at(*self.shots.last()?)?;
+        ship.is_sunk(&self.tiles).then_some(ship)
+    }
+
+    // the board before any shots were fired
+    fn initial(&self) -> Board {
+        let tiles = self
+            .tiles
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|&tile| match tile {
+                        Hit | Ship => Ship,
+                        Miss | Untried => Untried,
+                    })
+                    .collect()
+            })
+            .collect();
+        let moves_left = self
+            .moves_left
+            .map(|moves_left| moves_left + self.shots.len() as i32);
+        Board::from_fleet((tiles, self.ships.clone()), moves_left)
+    }
 
-    let max_hits: usize = SHIPS.iter().sum();
-    let total_hits: usize = board
+    fn diagram(&self, title: &'static str) -> Diagram {
+        let cells = self
+            .tiles
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|tile| match tile {
+                        Hit => "X",
+                        Miss => "O",
+                        Ship => "#",
+                        Untried => "",
+                    })
+                    .collect()
+            })
+            .collect();
+        Diagram { title, cells }
+    }
+}
+
+// a one-sided game, the Fleet tag holds the starting board as encoded by
+// `Game::encode`
+pub fn one_sided_record(name: &str, board: &Board) -> Record {
+    let mut record = Record::new("battleship", name, Mode::OneSided.name(), board.outcome());
+    record
+        .extra
+        .push(("Fleet".to_owned(), board.initial().encode()));
+    record.moves = board
+        .shots
         .iter()
-        .map(|row| row.iter().filter(|&&tile| tile == Hit).count())
-        .sum();
+        .map(|&(i, j)| record::square(i, j))
+        .collect();
+    record
+}
+
+// a two-sided game, the player's shots alternate with the server's and
+// PlayerFleet holds the player's starting board
+pub fn two_sided_record(name: &str, board: &Board, player_board: &Board) -> Record {
+    let result = two_sided_outcome(board, player_board);
+    let mut record = Record::new("battleship", name, Mode::TwoSided.name(), result);
+    record
+        .extra
+        .push(("Fleet".to_owned(), board.initial().encode()));
+    record
+        .extra
+        .push(("PlayerFleet".to_owned(), player_board.initial().encode()));
+    for (n, &shot) in board.shots.iter().enumerate() {
+        record.moves.push(record::square(shot.0, shot.1));
+        if let Some(&(i, j)) = player_board.shots.get(n) {
+            record.moves.push(record::square(i, j));
+        }
+    }
+    record
+}
+
+// the server's shot at the player's fleet in a two-sided game
+pub fn server_fire(player_board: &mut Board) -> Option<(usize, usize)> {
+    let target = choose_target(&player_board.tiles, &SHIPS)?;
+    player_board.apply_move(target).ok()?;
+    Some(target)
+}
+
+// X is the player, O the server
+pub fn two_sided_outcome(board: &Board, player_board: &Board) -> Outcome {
+    if board.outcome().is_over() {
+        Outcome::Win(Player::X)
+    } else if player_board.outcome().is_over() {
+        Outcome::Win(Player::O)
+    } else {
+        Outcome::InProgress
+    }
+}
+
+// every position of a recorded game
+pub fn replay(record: &Record) -> Result<Replay, String> {
+    let mode = Mode::parse(&record.variant)
+        .ok_or(format!("Unknown battleship mode \"{}\"", record.variant))?;
+    let fleet = |tag: &str| -> Result<Board, String> {
+        let encoding = record.tag(tag).ok_or(format!("Missing {tag} tag"))?;
+        let board = Board::decode(encoding).map_err(|err| format!("Invalid {tag}: {err}"))?;
+        if board
+            .tiles
+            .iter()
+            .flatten()
+            .any(|&tile| matches!(tile, Hit | Miss))
+        {
+            return Err(format!("{tag} must be the board before any shots"));
+        }
+        Ok(board)
+    };
+    let mut board = fleet("Fleet")?;
+    let mut player_board = match mode {
+        Mode::OneSided => None,
+        Mode::TwoSided => Some(fleet("PlayerFleet")?),
+    };
+    let diagrams = |board: &Board, player_board: &Option<Board>| {
+        let mut diagrams = vec![board.diagram("Enemy waters")];
+        if let Some(player_board) = player_board {
+            diagrams.push(player_board.diagram("Your fleet"));
+        }
+        diagrams
+    };
+    let outcome = |board: &Board, player_board: &Option<Board>| match player_board {
+        Some(player_board) => two_sided_outcome(board, player_board),
+        None => board.outcome(),
+    };
+
+    let mut positions = vec![diagrams(&board, &player_board)];
+    for (n, mv) in record.moves.iter().enumerate() {
+        let cell = record::parse_square(mv).ok_or(format!("Invalid move \"{mv}\""))?;
+        if outcome(&board, &player_board).is_over() {
+            return Err(format!("Move \"{mv}\" comes after the end of the game"));
+        }
+        // in two-sided games every second shot is the server's
+        let target = match player_board.as_mut() {
+            Some(player_board) if n % 2 == 1 => player_board,
+            _ => &mut board,
+        };
+        target
+            .apply_move(cell)
+            .map_err(|err| format!("Move \"{mv}\": {err}"))?;
+        positions.push(diagrams(&board, &player_board));
+    }
+    Ok(Replay {
+        outcome: outcome(&board, &player_board),
+        positions,
+    })
+}
+
+impl Game for Board {
+    type Move = (usize, usize);
+
+    fn new() -> Self {
+        Board::one_sided(&FleetOptions::default()).expect("Default fleet always fits")
+    }
+
+    fn current_player(&self) -> Player {
+        Player::X
+    }
+
+    fn legal_moves(&self) -> Vec<Self::Move> {
+        if self.outcome().is_over() {
+            return vec![];
+        }
+        let mut moves = vec![];
+        for (i, row) in self.tiles.iter().enumerate() {
+            for (j, tile) in row.iter().enumerate() {
+                if matches!(tile, Untried | Ship) {
+                    moves.push((i, j));
+                }
+            }
+        }
+        moves
+    }
+
+    fn apply_move(&mut self, (i, j): Self::Move) -> Result<(), String> {
+        if self.outcome().is_over() {
+            return Err("Game is over".to_string());
+        }
+        if i >= self.tiles.len() || j >= self.tiles[i].len() {
+            return Err("Cell is out of bounds".to_string());
+        }
+        if !fire(&mut self.tiles, (i, j)) {
+            return Err("Cell was already tried".to_string());
+        }
+        if let Some(moves_left) = self.moves_left.as_mut() {
+            *moves_left -= 1;
+        }
+        self.shots.push((i, j));
+        Ok(())
+    }
+
+    fn outcome(&self) -> Outcome {
+        if !self.tiles.iter().flatten().any(|&tile| tile == Ship) {
+            Outcome::Win(Player::X)
+        } else if self.moves_left.is_some_and(|moves_left| moves_left <= 0) {
+            Outcome::Win(Player::O)
+        } else {
+            Outcome::InProgress
+        }
+    }
+
+    // moves left ('-' when unlimited), then rows separated by '/' with
+    // 'X' hit, 'O' miss, '.' untried and '#' ship
+    fn encode(&self) -> String {
+        let moves_left = self
+            .moves_left
+            .map_or("-".to_owned(), |moves_left| moves_left.to_string());
+        let rows: Vec<String> = self
+            .tiles
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|tile| match tile {
+                        Hit => 'X',
+                        Miss => 'O',
+                        Untried => '.',
+                        Ship => '#',
+                    })
+                    .collect()
+            })
+            .collect();
+        format!("{moves_left}:{}", rows.join("/"))
+    }
+
+    fn decode(encoding: &str) -> Result<Self, String> {
+        let (moves_left, rows) = encoding
+            .split_once(':')
+            .ok_or("Missing moves left".to_string())?;
+        let moves_left = match moves_left {
+            "-" => None,
+            moves_left => Some(
+                moves_left
+                    .parse()
+                    .or(Err("Invalid moves left".to_string()))?,
+            ),
+        };
+        let tiles = rows
+            .split('/')
+            .map(|row| {
+                row.chars()
+                    .map(|c| match c {
+                        'X' => Ok(Hit),
+                        'O' => Ok(Miss),
+                        '.' => Ok(Untried),
+                        '#' => Ok(Ship),
+                        c => Err(format!("Invalid tile '{c}'")),
+                    })
+                    .collect::<Result<Vec<Tile>, String>>()
+            })
+            .collect::<Result<Vec<Vec<Tile>>, String>>()?;
+        Ok(Board::from_tiles(tiles, moves_left))
+    }
+}
+
+// `message` explains a rejected move, empty otherwise. `watch` is the
+// `watch_line` of the game
+pub fn make_board_page(name: String, board: Board, message: &str, watch: &str) -> String {
+    let time_formatted = Utc::now().format("%Y-%m-%d");
+
+    let outcome = board.outcome();
+    let moves_left = board.moves_left.unwrap_or_default();
+    let seed = seed_line(&board, outcome.is_over());
+    let message = message_line(message);
+    let sunk = board.last_sunk().map_or("".to_owned(), |ship| {
+        format!("You sunk the {}!<br/>", ship.name())
+    });
+    let fleet_panel = make_fleet_panel("Enemy fleet", &board);
+    let download = if outcome.is_over() {
+        one_sided_record(&name, &board).download_link()
+    } else {
+        "".to_owned()
+    };
 
     let mut table_rows = "".to_owned();
-    board.iter().enumerate().for_each(|(i, row)| {
+    board.tiles.iter().enumerate().for_each(|(i, row)| {
         table_rows += "<tr>";
         row.iter().enumerate().for_each(|(j, tile)| {
             table_rows += "<td>";
@@ -96,8 +723,10 @@ pub fn make_board_page(name: String, board: Vec<Vec<Tile>>, moves_left: i32) ->
                     table_rows += "X";
                 }
                 Miss => table_rows += "O",
+                // reveal the ships that were never found
+                Ship if outcome.is_over() => table_rows += "#",
                 Untried | Ship => {
-                    if moves_left > 0 && total_hits < max_hits {
+                    if !outcome.is_over() {
                         table_rows +=
                             format!(r#"<button type="submit" name="move" value={i},{j}>?</button>"#)
                                 .as_str()
@@ -119,12 +748,176 @@ pub fn make_board_page(name: String, board: Vec<Vec<Tile>>, moves_left: i32) ->
 "#
     );
 
-    let play_again = if total_hits == max_hits {
+    let play_again = match outcome {
+        Outcome::Win(Player::X) => {
+            r#"You win!
+<form action="/battleship.php" method="POST">
+    <input type="submit" name="play_again" value="Play again">
+</form>"#
+        }
+        Outcome::Win(Player::O) => {
+            r#"You lose!
+<form action="/battleship.php" method="POST">
+    <input type="submit" name="play_again" value="Play again">
+</form>"#
+        }
+        _ => "",
+    };
+
+    let name = html::escape(&name);
+    format!(
+        r#"
+<!DOCTYPE html>
+<html>
+<head>
+    <link rel="stylesheet" href="/battleship.css"/>
+<head>
+<body>
+    Hello {name}, {time_formatted}<br/>
+    Moves left: {moves_left}<br/>
+    {seed}
+    {message}
+    {sunk}
+    <form method="post">
+    {table}
+    </form>
+    {fleet_panel}
+    {play_again}
+    {download}
+    {watch}
+</body>
+</html>
+"#,
+    )
+}
+
+fn message_line(message: &str) -> String {
+    if message.is_empty() {
+        "".to_owned()
+    } else {
+        format!("{message}<br/>")
+    }
+}
+
+// the seed gives the fleet away, so it is only shown once the game is over
+fn seed_line(board: &Board, is_over: bool) -> String {
+    match board.seed {
+        Some(seed) if is_over => format!("Seed: {seed}<br/>"),
+        _ => "".to_owned(),
+    }
+}
+
+// every ship of a fleet and whether it is still afloat
+fn make_fleet_panel(title: &str, board: &Board) -> String {
+    if board.ships.is_empty() {
+        return "".to_owned();
+    }
+    let items: String = board
+        .ships
+        .iter()
+        .map(|ship| {
+            let status = if ship.is_sunk(&board.tiles) {
+                "sunk"
+            } else {
+                "afloat"
+            };
+            format!("<li>{}: {status}</li>", ship.name())
+        })
+        .collect();
+    format!(
+        r#"
+    <h4>{title}</h4>
+    <ul>{items}</ul>
+"#
+    )
+}
+
+// `reveal` shows the ships nobody has hit yet
+fn make_fleet_table(board: &[Vec<Tile>], reveal: bool) -> String {
+    let mut table_rows = "".to_owned();
+    board.iter().for_each(|row| {
+        table_rows += "<tr>";
+        row.iter().for_each(|tile| {
+            table_rows += match tile {
+                Hit => "<td>X</td>",
+                Miss => "<td>O</td>",
+                Ship if reveal => "<td>#</td>",
+                Untried | Ship => "<td></td>",
+            };
+        });
+        table_rows += "</tr>";
+    });
+    format!(
+        r#"
+    <table>
+    <tbody>
+    {table_rows}
+    </tbody>
+    </table>
+"#
+    )
+}
+
+pub fn make_two_sided_page(
+    name: String,
+    board: Board,
+    player_board: Board,
+    message: &str,
+    watch: &str,
+) -> String {
+    let time_formatted = Utc::now().format("%Y-%m-%d");
+
+    let player_won = board.outcome() == Outcome::Win(Player::X);
+    let server_won = player_board.outcome() == Outcome::Win(Player::X);
+    let is_over = player_won || server_won;
+
+    let mut table_rows = "".to_owned();
+    board.tiles.iter().enumerate().for_each(|(i, row)| {
+        table_rows += "<tr>";
+        row.iter().enumerate().for_each(|(j, tile)| {
+            table_rows += "<td>";
+            match tile {
+                Hit => table_rows += "X",
+                Miss => table_rows += "O",
+                // reveal the ships that were never found
+                Ship if is_over => table_rows += "#",
+                Untried | Ship => {
+                    if !is_over {
+                        table_rows +=
+                            format!(r#"<button type="submit" name="move" value={i},{j}>?</button>"#)
+                                .as_str()
+                    }
+                }
+            };
+            table_rows += "</td>";
+        });
+        table_rows += "</tr>";
+    });
+    let fleet_table = make_fleet_table(&player_board.tiles, true);
+    let seed = seed_line(&board, is_over);
+    let message = message_line(message);
+    let mut sunk = board.last_sunk().map_or("".to_owned(), |ship| {
+        format!("You sunk the {}!<br/>", ship.name())
+    });
+    // the server only fired this turn if it has as many shots as the player
+    let server_fired = player_board.shots.len() == board.shots.len();
+    if let (Some(ship), true) = (player_board.last_sunk(), server_fired) {
+        sunk += &format!("I sunk your {}!<br/>", ship.name());
+    }
+    let enemy_panel = make_fleet_panel("Enemy fleet", &board);
+    let player_panel = make_fleet_panel("Your ships", &player_board);
+    let download = if is_over {
+        two_sided_record(&name, &board, &player_board).download_link()
+    } else {
+        "".to_owned()
+    };
+
+    let play_again = if player_won {
         r#"You win!
 <form action="/battleship.php" method="POST">
     <input type="submit" name="play_again" value="Play again">
 </form>"#
-    } else if moves_left == 0 {
+    } else if server_won {
         r#"You lose!
 <form action="/battleship.php" method="POST">
     <input type="submit" name="play_again" value="Play again">
@@ -133,6 +926,7 @@ pub fn make_board_page(name: String, board: Vec<Vec<Tile>>, moves_left: i32) ->
         ""
     };
 
+    let name = html::escape(&name);
     format!(
         r#"
 <!DOCTYPE html>
@@ -142,13 +936,245 @@ pub fn make_board_page(name: String, board: Vec<Vec<Tile>>, moves_left: i32) ->
 <head>
 <body>
     Hello {name}, {time_formatted}<br/>
-    Moves left: {moves_left}
+    {seed}
+    {message}
+    {sunk}
+    <h3>Enemy waters</h3>
     <form method="post">
-    {table}
+    <table>
+    <tbody>
+    {table_rows}
+    </tbody>
+    </table>
     </form>
+    {enemy_panel}
+    <h3>Your fleet</h3>
+    {fleet_table}
+    {player_panel}
     {play_again}
+    {download}
+    {watch}
+</body>
+</html>
+"#,
+    )
+}
+
+// the line on a player's page that points spectators at the game
+pub fn watch_line(id: &str, watchers: usize) -> String {
+    format!(
+        r#"Spectators: <a href="/battleship.php/watch/{id}">watch this game</a>, {watchers} watching<br/>"#
+    )
+}
+
+// a read-only view of someone's game for spectators. ships stay hidden until
+// the game is over and the seed is left out since it gives the fleet away.
+// `player_board` is the player's own fleet in a two-sided game
+pub fn make_spectator_page(
+    name: &str,
+    board: &Board,
+    player_board: Option<&Board>,
+    watchers: usize,
+) -> String {
+    let name = html::escape(name);
+    let outcome = match player_board {
+        Some(player_board) => two_sided_outcome(board, player_board),
+        None => board.outcome(),
+    };
+    let is_over = outcome.is_over();
+    let status = match outcome {
+        Outcome::Win(Player::X) => format!("{name} won!"),
+        Outcome::Win(Player::O) => format!("{name} lost!"),
+        _ => format!("{name} is playing"),
+    };
+    // spectators have no moves to make, they poll until the game is over
+    let refresh = if is_over {
+        ""
+    } else {
+        r#"<meta http-equiv="refresh" content="2"/>"#
+    };
+    let moves_left = board.moves_left.map_or("".to_owned(), |moves_left| {
+        format!("Moves left: {moves_left}<br/>")
+    });
+    let enemy_table = make_fleet_table(&board.tiles, is_over);
+    let enemy_panel = make_fleet_panel("Enemy fleet", board);
+    let player_fleet = player_board.map_or("".to_owned(), |player_board| {
+        format!(
+            r#"
+    <h3>{name}'s fleet</h3>
+    {}
+    {}
+"#,
+            make_fleet_table(&player_board.tiles, is_over),
+            make_fleet_panel(&format!("{name}'s ships"), player_board)
+        )
+    });
+
+    format!(
+        r#"
+<!DOCTYPE html>
+<html>
+<head>
+    <link rel="stylesheet" href="/battleship.css"/>
+    {refresh}
+<head>
+<body>
+    {status}<br/>
+    {moves_left}
+    Watching: {watchers}<br/>
+    <h3>Enemy waters</h3>
+    {enemy_table}
+    {enemy_panel}
+    {player_fleet}
 </body>
 </html>
 "#,
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ship_tiles(tiles: &[Vec<Tile>]) -> usize {
+        tiles.iter().flatten().filter(|&&tile| tile == Ship).count()
+    }
+
+    // whether any cell of one ship is next to a cell of another, diagonals
+    // included
+    fn ships_touch(ships: &[PlacedShip]) -> bool {
+        ships.iter().enumerate().any(|(a, first)| {
+            ships[a + 1..].iter().any(|second| {
+                first.cells.iter().any(|&(i, j)| {
+                    second
+                        .cells
+                        .iter()
+                        .any(|&(k, l)| i.abs_diff(k) <= 1 && j.abs_diff(l) <= 1)
+                })
+            })
+        })
+    }
+
+    #[test]
+    fn same_seed_places_same_fleet() {
+        for no_touch in [false, true] {
+            let first = create_battleship_game(ROWS, COLS, &SHIPS, 42, no_touch).unwrap();
+            let second = create_battleship_game(ROWS, COLS, &SHIPS, 42, no_touch).unwrap();
+            assert_eq!(first, second);
+        }
+        let fleets: Vec<Fleet> = (0..5)
+            .map(|seed| create_battleship_game(ROWS, COLS, &SHIPS, seed, false).unwrap())
+            .collect();
+        assert!(fleets.iter().any(|fleet| *fleet != fleets[0]));
+    }
+
+    #[test]
+    fn places_every_ship() {
+        for seed in 0..20 {
+            let (tiles, ships) = create_battleship_game(ROWS, COLS, &SHIPS, seed, false).unwrap();
+            let mut lengths: Vec<usize> = ships.iter().map(|ship| ship.length).collect();
+            lengths.sort_unstable();
+            assert_eq!(lengths, SHIPS);
+            for ship in &ships {
+                assert_eq!(ship.cells.len(), ship.length);
+            }
+            assert_eq!(ship_tiles(&tiles), SHIPS.iter().sum::<usize>());
+        }
+    }
+
+    #[test]
+    fn no_touch_fleets_keep_apart() {
+        for seed in 0..50 {
+            let (_, ships) = create_battleship_game(ROWS, COLS, &SHIPS, seed, true).unwrap();
+            assert!(!ships_touch(&ships), "seed {seed}");
+        }
+    }
+
+    #[test]
+    fn impossible_fleet_is_an_error() {
+        assert!(create_battleship_game(2, 2, &[2, 2, 2], 0, false).is_err());
+        assert!(create_battleship_game(2, 2, &[2, 2], 0, false).is_ok());
+        assert!(create_battleship_game(2, 2, &[2, 2], 0, true).is_err());
+    }
+
+    #[test]
+    fn hand_placed_fleet() {
+        let (tiles, ships) = place_fleet(ROWS, COLS, &SHIPS, "0,0,h; 2,0,h; 4,0,H", true).unwrap();
+        assert_eq!(ship_tiles(&tiles), 9);
+        assert_eq!(ships[2].cells, vec![(4, 0), (4, 1), (4, 2), (4, 3)]);
+
+        let touching = "0,0,h;1,0,h;3,0,h";
+        assert!(place_fleet(ROWS, COLS, &SHIPS, touching, false).is_ok());
+        let err = place_fleet(ROWS, COLS, &SHIPS, touching, true).unwrap_err();
+        assert!(err.contains("touches"), "{err}");
+    }
+
+    #[test]
+    fn bad_fleet_text_is_an_error() {
+        for fleet in [
+            "",
+            "0,0,h;2,0,h",
+            "0,0,h;2,0,h;4,0",
+            "0,0,h;2,x,h;4,0,h",
+            "0,0,h;2,0,d;4,0,h",
+            "0,0,h;2,0,h;4,5,h",
+            "0,0,h;0,0,v;4,0,h",
+        ] {
+            assert!(
+                place_fleet(ROWS, COLS, &SHIPS, fleet, false).is_err(),
+                "{fleet:?}"
+            );
+        }
+    }
+
+    fn seeded_board() -> Board {
+        Board::one_sided(&FleetOptions {
+            seed: Some(7),
+            no_touch: false,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn bad_coordinates_are_rejected() {
+        for value in ["a,b", "1", "", ",", "9,9", "5,0", "0,7", "-1,0", "1,2,3"] {
+            assert!(Coordinate::parse(value, ROWS, COLS).is_err(), "{value:?}");
+        }
+        assert_eq!(
+            Coordinate::parse(" 4, 6", ROWS, COLS),
+            Ok(Coordinate { row: 4, col: 6 })
+        );
+    }
+
+    #[test]
+    fn a_repeated_shot_costs_nothing() {
+        let mut board = seeded_board();
+        board.apply_move((0, 0)).unwrap();
+        let (moves_left, shots) = (board.moves_left, board.shots.clone());
+        assert!(board.apply_move((0, 0)).is_err());
+        assert!(board.apply_move((ROWS, 0)).is_err());
+        assert_eq!(board.moves_left, moves_left);
+        assert_eq!(board.shots, shots);
+    }
+
+    #[test]
+    fn a_shot_after_the_game_costs_nothing() {
+        let mut board = seeded_board();
+        let ships: Vec<(usize, usize)> = (0..ROWS)
+            .flat_map(|i| (0..COLS).map(move |j| (i, j)))
+            .filter(|&(i, j)| board.tiles[i][j] == Ship)
+            .collect();
+        for cell in ships {
+            board.apply_move(cell).unwrap();
+        }
+        assert_eq!(board.outcome(), Outcome::Win(Player::X));
+        let (moves_left, shots) = (board.moves_left, board.shots.clone());
+        let untried = (0..ROWS)
+            .flat_map(|i| (0..COLS).map(move |j| (i, j)))
+            .find(|&(i, j)| board.tiles[i][j] == Untried)
+            .unwrap();
+        assert_eq!(board.apply_move(untried), Err("Game is over".to_owned()));
+        assert_eq!(board.moves_left, moves_left);
+        assert_eq!(board.shots, shots);
+    }
+}
@@ -1,121 +1,277 @@
 // code for connect4 game
-#![allow(clippy::needless_range_loop)]
 use dioxus::prelude::*;
-use rand::prelude::*;
 use std::fmt;
+use std::str::FromStr;
+
+pub use crate::connect_engine::DEFAULT_DEPTH;
+use crate::connect_engine::{Position, MAX_DEPTH};
+use crate::game::{Game, Outcome, Player};
+use crate::html;
+use crate::mnk::Grid;
+pub use crate::mnk::{ParseError, Variant};
+use crate::record::{Diagram, Record, Replay};
+use crate::signing::Signer;
 
 #[derive(Debug, Clone, PartialEq)]
-struct Board {
-    chips: [[String; 7]; 5],
+pub struct Board {
+    grid: Grid,
+    // columns played to reach this board, oldest first, empty if unknown
+    history: Vec<usize>,
 }
 
 impl Board {
-    fn new() -> Self {
+    pub fn with_variant(variant: Variant) -> Self {
         Board {
-            chips: Default::default(),
+            grid: Grid::new(variant),
+            history: vec![],
         }
     }
 
-    fn from(encoding: &str) -> Self {
-        let mut chips: [[String; 7]; 5] = Default::default();
+    // rows are separated by dots and the cells of a row by single spaces
+    pub fn parse(variant: Variant, encoding: &str) -> Result<Self, ParseError> {
         let encoding = encoding.replace('\n', "");
         let rows: Vec<&str> = encoding.split('.').collect();
+        if rows.len() != variant.rows {
+            return Err(ParseError::WrongLength {
+                expected: variant.rows,
+                found: rows.len(),
+            });
+        }
+        let mut symbols = vec![];
         for (i, row) in rows.iter().enumerate() {
             let cols: Vec<&str> = row.split(' ').collect();
-            for (j, col) in cols.iter().enumerate() {
-                if !col.is_empty() {
-                    chips[i][j] = col.to_string();
-                }
+            if cols.len() != variant.cols {
+                return Err(ParseError::WrongRowLength {
+                    row: i,
+                    expected: variant.cols,
+                    found: cols.len(),
+                });
             }
+            symbols.extend(cols);
         }
-        Board { chips }
+        Ok(Board {
+            grid: Grid::from_symbols(variant, symbols)?,
+            history: vec![],
+        })
     }
 
-    fn make_move(&mut self, col: usize, player: &str) -> Result<(), String> {
-        for i in (0..5).rev() {
-            if self.chips[i][col].is_empty() {
-                self.chips[i][col] = player.to_string();
-                return Ok(());
-            }
+    pub(crate) fn grid(&self) -> &Grid {
+        &self.grid
+    }
+
+    // columns separated by dots, e.g. "3.3.2"
+    pub fn history(&self) -> String {
+        self.history
+            .iter()
+            .map(usize::to_string)
+            .collect::<Vec<String>>()
+            .join(".")
+    }
+
+    fn replay(variant: Variant, moves: &[usize]) -> Option<Board> {
+        let mut board = Board::with_variant(variant);
+        for &col in moves {
+            board.apply_move(col).ok()?;
         }
-        Err("Column is full".to_string())
+        Some(board)
     }
 
-    fn is_full(&self) -> bool {
-        for i in 0..7 {
-            if self.chips[0][i].is_empty() {
-                return false;
-            }
+    // attaches a move list to a parsed board, it is dropped unless replaying
+    // it from an empty board gives exactly this board
+    pub fn with_history(self, encoding: &str) -> Board {
+        let moves: Option<Vec<usize>> = encoding
+            .split('.')
+            .filter(|col| !col.is_empty())
+            .map(|col| col.parse().ok())
+            .collect();
+        match moves.and_then(|moves| Board::replay(self.grid.variant(), &moves)) {
+            Some(board) if board.grid == self.grid => board,
+            _ => self,
         }
-        true
     }
 
-    fn has_win(&self) -> Option<&str> {
-        // across, down, diagonal
-        const DX: [i32; 4] = [0, 1, 1, 1];
-        const DY: [i32; 4] = [1, 0, 1, -1];
-        for i in 0..5 {
-            for j in 0..7 {
-                if self.chips[i][j].is_empty() {
-                    continue;
-                }
-                for k in 0..DX.len() {
-                    let mut count = 0;
-                    for l in 0..4 {
-                        let (nx, ny) = ((i as i32) + DX[k] * l, (j as i32) + DY[k] * l);
-                        let in_bounds = (0..5).contains(&nx) && (0..7).contains(&ny);
-                        if !in_bounds {
-                            break;
-                        }
-                        if self.chips[nx as usize][ny as usize] == self.chips[i][j] {
-                            count += 1;
-                        }
-                    }
-                    if count == 4 {
-                        return Some(&self.chips[i][j]);
-                    }
+    // the board before the last X/O pair, or before X's last move if the
+    // server never got to reply, so it is X's turn again
+    pub fn undo(&self) -> Option<Board> {
+        if self.history.is_empty() {
+            return None;
+        }
+        let keep = (self.history.len() - 1) / 2 * 2;
+        Board::replay(self.grid.variant(), &self.history[..keep])
+    }
+
+    // moves played so far, 0 if the history is unknown
+    pub fn move_count(&self) -> usize {
+        self.history.len()
+    }
+
+    // moves are written as column numbers counted from 1
+    pub fn record(&self, name: &str) -> Record {
+        let mut record = Record::new("connect", name, self.grid.variant().name(), self.outcome());
+        record.moves = self
+            .history
+            .iter()
+            .map(|col| (col + 1).to_string())
+            .collect();
+        record
+    }
+
+    // the bitboard engine handles every board that fits in a u64
+    fn to_position(&self) -> Option<Position> {
+        let variant = self.grid.variant();
+        if variant.win_length != 4 || !Position::supports(variant.cols, variant.rows) {
+            return None;
+        }
+        let position = Position::new(variant.cols, variant.rows);
+        let (mut x, mut o) = (0, 0);
+        for (i, row) in self.grid.rows().enumerate() {
+            for (j, cell) in row.iter().enumerate() {
+                match cell {
+                    Some(Player::X) => x |= position.cell_bit(i, j),
+                    Some(Player::O) => o |= position.cell_bit(i, j),
+                    None => {}
                 }
             }
         }
-        None
+        Some(Position::from_masks(variant.cols, variant.rows, x, o))
+    }
+
+    // plays the server's answer to X's move, returns it unless the game is
+    // over or it is not the server's turn
+    pub fn reply(&mut self, depth: u32) -> Option<usize> {
+        if self.outcome().is_over() || self.current_player() != Player::O {
+            return None;
+        }
+        let col = self.choose_move(depth)?;
+        self.apply_move(col).ok()?;
+        Some(col)
+    }
+
+    fn choose_move(&self, depth: u32) -> Option<usize> {
+        match self.to_position() {
+            Some(position) => position.best_move(depth),
+            None => self
+                .grid
+                .heuristic_move(self.current_player())
+                .map(|(_, col)| col),
+        }
     }
 
     fn get_state(&self) -> &'static str {
-        match self.has_win() {
-            Some("X") => "You won!",
-            Some("O") => "I won!",
-            Some(_) => panic!("Invalid state"),
-            None => {
-                if self.is_full() {
-                    "Draw"
-                } else {
-                    ""
-                }
-            }
+        match self.outcome() {
+            Outcome::Win(Player::X) => "You won!",
+            Outcome::Win(Player::O) => "I won!",
+            Outcome::Draw => "Draw",
+            Outcome::InProgress => "",
         }
     }
 }
 
+impl Game for Board {
+    type Move = usize;
+
+    fn new() -> Self {
+        Board::with_variant(Variant::CONNECT_FOUR)
+    }
+
+    fn current_player(&self) -> Player {
+        self.grid.current_player()
+    }
+
+    fn legal_moves(&self) -> Vec<Self::Move> {
+        if self.outcome().is_over() {
+            return vec![];
+        }
+        self.grid
+            .open_cells()
+            .into_iter()
+            .map(|(_, col)| col)
+            .collect()
+    }
+
+    fn apply_move(&mut self, col: Self::Move) -> Result<(), String> {
+        if col >= self.grid.variant().cols {
+            return Err("Column is out of bounds".to_string());
+        }
+        let row = self.grid.landing_row(col).ok_or("Column is full")?;
+        let player = self.current_player();
+        self.grid.place(row, col, player)?;
+        self.history.push(col);
+        Ok(())
+    }
+
+    fn outcome(&self) -> Outcome {
+        self.grid.outcome()
+    }
+
+    fn encode(&self) -> String {
+        self.to_string()
+    }
+
+    fn decode(encoding: &str) -> Result<Self, String> {
+        encoding.parse().map_err(|err: ParseError| err.to_string())
+    }
+}
+
 impl fmt::Display for Board {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let mut rows: [String; 5] = Default::default();
-        for (i, row) in self.chips.iter().enumerate() {
-            rows[i] = row.join(" ");
-        }
+        let rows: Vec<String> = self
+            .grid
+            .rows()
+            .map(|row| {
+                row.iter()
+                    .map(|cell| cell.map_or("", Player::symbol))
+                    .collect::<Vec<&str>>()
+                    .join(" ")
+            })
+            .collect();
         write!(f, "{}", rows.join("."))
     }
 }
 
+// the variant is inferred from the number of rows and columns
+impl FromStr for Board {
+    type Err = ParseError;
+
+    fn from_str(encoding: &str) -> Result<Self, Self::Err> {
+        let rows = encoding.split('.').count();
+        let cols = encoding.split('.').next().unwrap_or("").split(' ').count();
+        let variant = Variant::all(true)
+            .find(|variant| variant.rows == rows && variant.cols == cols)
+            .unwrap_or(Variant::CONNECT_FOUR);
+        Board::parse(variant, encoding)
+    }
+}
+
+// connect4 variants are the ones with gravity
+pub fn variant_from_name(name: &str) -> Option<Variant> {
+    Variant::from_name(name).filter(|variant| variant.gravity)
+}
+
 #[derive(Debug, Clone, PartialEq, Props)]
 struct GameProps {
     name: String,
     board: Board,
+    depth: u32,
+    #[props(!optional)]
+    signer: Option<Signer>,
 }
 
 #[derive(Debug, Clone, PartialEq, Props)]
 struct PlayProps {
     name: String,
-    encoding: String,
+    board: Board,
+    depth: u32,
+    #[props(!optional)]
+    signer: Option<Signer>,
+}
+
+#[derive(Debug, Clone, PartialEq, Props)]
+struct BadBoardProps {
+    name: String,
+    depth: u32,
+    variant: Variant,
+    error: ParseError,
 }
 
 #[component]
@@ -130,6 +286,22 @@ fn Home(cx: Scope) -> Element {
                 method: "POST",
                 label { r#for: "name", "Name:"}
                 input { id: "name", name: "name", r#type: "text", required: true }
+                label { r#for: "depth", "Search depth:"}
+                input {
+                    id: "depth",
+                    name: "depth",
+                    r#type: "number",
+                    min: "1",
+                    max: "{MAX_DEPTH}",
+                    value: "{DEFAULT_DEPTH}"
+                }
+                label { r#for: "variant", "Board:"}
+                select {
+                    id: "variant",
+                    name: "variant",
+                    option { value: "5x7", selected: true, "5x7" }
+                    option { value: "6x7", "6x7" }
+                }
                 input { r#type: "submit", value: "Submit" }
             }
         }
@@ -138,65 +310,111 @@ fn Home(cx: Scope) -> Element {
 
 #[component]
 fn Game(cx: Scope<GameProps>) -> Element {
-    let mut states: [String; 7] = Default::default();
-    for i in 0..7 {
-        let mut board = cx.props.board.clone();
-        match board.make_move(i, "X") {
-            Ok(()) => states[i] = board.to_string(),
-            Err(_) => continue,
+    let board = &cx.props.board;
+    let variant = board.grid.variant();
+    let mut states: Vec<(String, String, Option<String>)> =
+        vec![(String::new(), String::new(), None); variant.cols];
+    for (_, j) in board.grid.open_cells() {
+        let mut next = board.clone();
+        if next.apply_move(j).is_ok() {
+            states[j] = board_fields(&next, &cx.props.signer);
         }
     }
+    let undo = board
+        .undo()
+        .map(|previous| board_fields(&previous, &cx.props.signer));
+
+    // dioxus-ssr leaves attribute values as they are
+    let name = &html::escape(&cx.props.name);
 
     cx.render(rsx! {
         body {
-            form {
-                id: "game-form",
-                action: "/connect.php",
-                method: "POST",
-                input { r#type: "hidden", name: "name", value: "{cx.props.name}" }
-            }
-            table {
-                thead {
-                    tr {
-                        states.iter().enumerate().map(|(i, state)| {
+            // one form per column so each move carries its own signature
+            states.iter().enumerate().filter(|(_, (state, _, _))| !state.is_empty()).map(|(i, (_, moves, sig))| {
+                rsx! {
+                    form {
+                        id: "move-{i}",
+                        action: "/connect.php",
+                        method: "POST",
+                        input { r#type: "hidden", name: "name", value: "{name}" }
+                        input { r#type: "hidden", name: "depth", value: "{cx.props.depth}" }
+                        input { r#type: "hidden", name: "variant", value: "{variant}" }
+                        input { r#type: "hidden", name: "moves", value: "{moves}" }
+                        if let Some(sig) = sig {
+                            rsx! { input { r#type: "hidden", name: "sig", value: "{sig}" } }
+                        }
+                    }
+                }
+            })
+            div {
+                display: "flex",
+                gap: "20px",
+                table {
+                    thead {
+                        tr {
+                            states.iter().enumerate().map(|(i, (state, _, _))| {
+                                rsx! {
+                                    th {
+                                        if !state.is_empty() {
+                                            rsx! {
+                                                button {
+                                                    form: "move-{i}",
+                                                    r#type: "submit",
+                                                    name: "board",
+                                                    value: "{state}",
+                                                    "{i+1}"
+                                                }
+                                            }
+                                        }
+                                    }
+                                }
+                            })
+                        }
+                    }
+                    tbody {
+                        board.grid.rows().map(|row| {
                             rsx! {
-                                th {
-                                    if !state.is_empty() {
+                                tr {
+                                    row.iter().map(|cell| {
                                         rsx! {
-                                            button {
-                                                form: "game-form",
-                                                r#type: "submit",
-                                                name: "board",
-                                                value: "{state}",
-                                                "{i+1}"
+                                            td {
+                                                width: "50px",
+                                                height: "50px",
+                                                border: "1px solid black",
+                                                text_align: "center",
+                                                cell.map_or("", Player::symbol)
                                             }
                                         }
-                                    }
+                                    })
                                 }
                             }
                         })
                     }
                 }
-                tbody {
-                    (0..5).map(|i| {
-                        rsx! {
-                            tr {
-                                (0..7).map(|j| {
-                                    rsx! {
-                                        td {
-                                            width: "50px",
-                                            height: "50px",
-                                            border: "1px solid black",
-                                            text_align: "center",
-                                            cx.props.board.chips[i][j].as_str()
-                                        }
-                                    }
-                                })
-                            }
-                        }
+                ol {
+                    board.history.iter().enumerate().map(|(n, col)| {
+                        let player = if n % 2 == 0 { Player::X } else { Player::O };
+                        rsx! { li { "{player}: column {col + 1}" } }
                     })
                 }
             }
+            if let Some((state, moves, sig)) = undo {
+                rsx! {
+                    form {
+                        action: "/connect.php",
+                        method: "POST",
+                        input { r#type: "hidden", name: "name", value: "{name}" }
+                        input { r#type: "hidden", name: "depth", value: "{cx.props.depth}" }
+                        input { r#type: "hidden", name: "variant", value: "{variant}" }
+                        input { r#type: "hidden", name: "board", value: "{state}" }
+                        input { r#type: "hidden", name: "moves", value: "{moves}" }
+                        if let Some(sig) = sig {
+                            rsx! { input { r#type: "hidden", name: "sig", value: "{sig}" } }
+                        }
+                        input { r#type: "submit", value: "Undo" }
+                    }
+                }
+            }
         }
     })
 }
@@ -205,42 +423,57 @@ fn Game(cx: Scope<GameProps>) -> Element {
 fn Play(cx: Scope<PlayProps>) -> Element {
     let name = cx.props.name.to_string();
     let date = chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
-    let board = match cx.props.encoding.as_str() {
-        "" => Board::new(),
-        encoding => {
-            let mut board = Board::from(encoding);
-            if board.get_state().is_empty() {
-                let mut rng = rand::thread_rng();
-                let mut cols = vec![];
-                for i in 0..7 {
-                    if board.chips[0][i].is_empty() {
-                        cols.push(i);
-                    }
-                }
-                let col = cols.choose(&mut rng).unwrap();
-                board.make_move(*col, "O").expect("Invalid move");
-            }
-            board
-        }
-    };
+    let board = cx.props.board.clone();
+    let variant = board.grid.variant();
     let state = board.get_state();
+    let record = board.record(&name);
+    let escaped_name = html::escape(&name);
 
     cx.render(rsx! {
         p { "Hello {name}, {date}" }
         if !state.is_empty() {
             rsx! { p { "{state}" } }
         }
-        Game { name: name, board: board }
+        Game {
+            name: name,
+            board: board,
+            depth: cx.props.depth,
+            signer: cx.props.signer.clone()
+        }
         if !state.is_empty() {
             rsx! {
                 form {
                     action: "/connect.php",
                     method: "POST",
-                    input { r#type: "hidden", name: "name", value: "{cx.props.name}" }
+                    input { r#type: "hidden", name: "name", value: "{escaped_name}" }
+                    input { r#type: "hidden", name: "depth", value: "{cx.props.depth}" }
+                    input { r#type: "hidden", name: "variant", value: "{variant}" }
                     input { r#type: "submit", value: "Play Again" }
                 }
             }
         }
+        if !state.is_empty() && !record.moves.is_empty() {
+            rsx! {
+                a { download: "{record.file_name()}", href: "{record.data_url()}", "Download game" }
+            }
+        }
+    })
+}
+
+#[component]
+fn BadBoard(cx: Scope<BadBoardProps>) -> Element {
+    let error = cx.props.error.to_string();
+    let name = &html::escape(&cx.props.name);
+    cx.render(rsx! {
+        p { "Invalid board: {error}" }
+        form {
+            action: "/connect.php",
+            method: "POST",
+            input { r#type: "hidden", name: "name", value: "{name}" }
+            input { r#type: "hidden", name: "depth", value: "{cx.props.depth}" }
+            input { r#type: "hidden", name: "variant", value: "{cx.props.variant}" }
+            input { r#type: "submit", value: "Start over" }
+        }
     })
 }
 
@@ -253,11 +486,278 @@ pub fn get_form_html() -> String {
     )
 }
 
-pub fn accept_from_html(name: String, encoding: String) -> String {
-    let mut app = VirtualDom::new_with_props(Play, PlayProps { name, encoding });
+fn signing_scope(variant: Variant) -> String {
+    format!("connect:{variant}")
+}
+
+// the board, move list and, with a signer, board signature for a move form
+fn board_fields(board: &Board, signer: &Option<Signer>) -> (String, String, Option<String>) {
+    let state = board.to_string();
+    let sig = signer
+        .as_ref()
+        .map(|signer| signer.sign(&signing_scope(board.grid.variant()), &state));
+    (state, board.history(), sig)
+}
+
+// true if `signature` was produced by `signer` for this board
+pub fn verify_board(signer: &Signer, variant: Variant, encoding: &str, signature: &str) -> bool {
+    signer.verify(&signing_scope(variant), encoding, signature)
+}
+
+// every position of a recorded game
+pub fn replay(record: &Record) -> Result<Replay, String> {
+    let variant = variant_from_name(&record.variant)
+        .ok_or(format!("Unknown Connect-4 variant \"{}\"", record.variant))?;
+    let mut board = Board::with_variant(variant);
+    let diagram = |board: &Board| {
+        vec![Diagram {
+            title: "Board",
+            cells: board.grid.symbols(),
+        }]
+    };
+    let mut positions = vec![diagram(&board)];
+    for mv in &record.moves {
+        let col = mv
+            .parse::<usize>()
+            .ok()
+            .and_then(|col| col.checked_sub(1))
+            .ok_or(format!("Invalid move \"{mv}\""))?;
+        if board.outcome().is_over() {
+            return Err(format!("Move \"{mv}\" comes after the end of the game"));
+        }
+        board
+            .apply_move(col)
+            .map_err(|err| format!("Move \"{mv}\": {err}"))?;
+        positions.push(diagram(&board));
+    }
+    Ok(Replay {
+        positions,
+        outcome: board.outcome(),
+    })
+}
+
+// an empty encoding starts a new game, board buttons are signed when a signer
+// is given. `moves` is the move list from the previous page, if any. the
+// game's record comes with the page once the game is over
+pub fn accept_from_html(
+    name: String,
+    encoding: String,
+    moves: &str,
+    depth: u32,
+    variant: Variant,
+    signer: Option<Signer>,
+) -> Result<(String, Option<Record>), ParseError> {
+    let depth = depth.clamp(1, MAX_DEPTH);
+    let mut board = match encoding.as_str() {
+        "" => Board::with_variant(variant),
+        encoding => Board::parse(variant, encoding)?.with_history(moves),
+    };
+    // the server only replies once X has made a move
+    board.reply(depth);
+    let finished = board.outcome().is_over().then(|| board.record(&name));
+    let mut app = VirtualDom::new_with_props(
+        Play,
+        PlayProps {
+            name,
+            board,
+            depth,
+            signer,
+        },
+    );
+    let _ = app.rebuild();
+    Ok((
+        format!(
+            "<!DOCTYPE html><html lang='en'>{}</html",
+            dioxus_ssr::render(&app)
+        ),
+        finished,
+    ))
+}
+
+pub fn parse_error_html(name: String, depth: u32, variant: Variant, error: ParseError) -> String {
+    let mut app = VirtualDom::new_with_props(
+        BadBoard,
+        BadBoardProps {
+            name,
+            depth,
+            variant,
+            error,
+        },
+    );
     let _ = app.rebuild();
     format!(
         "<!DOCTYPE html><html lang='en'>{}</html",
         dioxus_ssr::render(&app)
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a 5x7 encoding from its rows, top row first, with - for an empty cell
+    fn encoding(rows: [&str; 5]) -> String {
+        rows.map(|row| {
+            row.chars()
+                .map(|cell| {
+                    if cell == '-' {
+                        String::new()
+                    } else {
+                        cell.to_string()
+                    }
+                })
+                .collect::<Vec<String>>()
+                .join(" ")
+        })
+        .join(".")
+    }
+
+    const EMPTY: &str = "-------";
+
+    #[test]
+    fn parses_and_prints_the_same_board() {
+        let text = encoding([EMPTY, EMPTY, EMPTY, "---O---", "--XX-O-"]);
+        let board = Board::parse(Variant::CONNECT_FOUR, &text).unwrap();
+        assert_eq!(board.to_string(), text);
+        assert_eq!(text.parse::<Board>().unwrap(), board);
+        assert_eq!(
+            Board::parse(Variant::CONNECT_FOUR, &encoding([EMPTY; 5])).unwrap(),
+            Board::with_variant(Variant::CONNECT_FOUR)
+        );
+    }
+
+    #[test]
+    fn wrong_length() {
+        let text = encoding([EMPTY; 5]);
+        let text = text.split_once('.').unwrap().1;
+        assert_eq!(
+            Board::parse(Variant::CONNECT_FOUR, text),
+            Err(ParseError::WrongLength {
+                expected: 5,
+                found: 4
+            })
+        );
+    }
+
+    #[test]
+    fn wrong_row_length() {
+        let text = encoding([EMPTY, EMPTY, "------", EMPTY, EMPTY]);
+        assert_eq!(
+            Board::parse(Variant::CONNECT_FOUR, &text),
+            Err(ParseError::WrongRowLength {
+                row: 2,
+                expected: 7,
+                found: 6
+            })
+        );
+    }
+
+    #[test]
+    fn bad_symbol() {
+        let text = encoding([EMPTY, EMPTY, EMPTY, EMPTY, "---x---"]);
+        assert_eq!(
+            Board::parse(Variant::CONNECT_FOUR, &text),
+            Err(ParseError::BadSymbol {
+                symbol: "x".to_owned(),
+                cell: 31
+            })
+        );
+    }
+
+    #[test]
+    fn floating_piece() {
+        let text = encoding([EMPTY, EMPTY, EMPTY, "---X---", "--O----"]);
+        assert_eq!(
+            Board::parse(Variant::CONNECT_FOUR, &text),
+            Err(ParseError::FloatingPiece { row: 3, col: 3 })
+        );
+    }
+
+    #[test]
+    fn impossible_counts() {
+        let text = encoding([EMPTY, EMPTY, EMPTY, EMPTY, "X-X----"]);
+        assert_eq!(
+            Board::parse(Variant::CONNECT_FOUR, &text),
+            Err(ParseError::ImpossibleCounts { x: 2, o: 0 })
+        );
+    }
+
+    #[test]
+    fn both_players_win() {
+        let text = encoding([EMPTY, EMPTY, EMPTY, "OOOO---", "XXXX---"]);
+        assert_eq!(
+            Board::parse(Variant::CONNECT_FOUR, &text),
+            Err(ParseError::BothPlayersWin)
+        );
+    }
+
+    #[test]
+    fn forms_escape_the_name() {
+        let name = "\"><script>x</script>".to_owned();
+        let page = parse_error_html(
+            name.clone(),
+            4,
+            Variant::CONNECT_FOUR,
+            ParseError::BothPlayersWin,
+        );
+        assert!(
+            page.contains(r#"value="&quot;&gt;&lt;script&gt;x&lt;/script&gt;""#),
+            "{page}"
+        );
+        assert!(!page.contains("<script"), "{page}");
+
+        let (page, _) =
+            accept_from_html(name, String::new(), "", 4, Variant::CONNECT_FOUR, None).unwrap();
+        assert!(!page.contains("<script"), "{page}");
+    }
+
+    // a 5x7 board reached by dropping into `moves`, with its history
+    fn played(moves: &[usize]) -> Board {
+        Board::replay(Variant::CONNECT_FOUR, moves).unwrap()
+    }
+
+    // the same board as a client sends it back, without a history
+    fn bare(board: &Board) -> Board {
+        Board::parse(Variant::CONNECT_FOUR, &board.to_string()).unwrap()
+    }
+
+    #[test]
+    fn a_history_that_replays_is_kept() {
+        let board = played(&[3, 3, 2]);
+        assert_eq!(board.history(), "3.3.2");
+        let restored = bare(&board).with_history("3.3.2");
+        assert_eq!(restored, board);
+        assert_eq!(restored.move_count(), 3);
+    }
+
+    #[test]
+    fn a_history_that_does_not_replay_is_dropped() {
+        let board = bare(&played(&[3, 3, 2]));
+        for history in [
+            // too short, X and O swapped
+            "3.3", "2.3.3", // off the board or not moves at all
+            "3.3.7", "3.3.-1", "3.3.two", "3,3,2", "garbage",
+        ] {
+            let restored = board.clone().with_history(history);
+            assert_eq!(restored, board, "{history:?}");
+            assert_eq!(restored.move_count(), 0);
+            assert_eq!(restored.undo(), None);
+        }
+        // a full column takes no more pieces
+        let full = bare(&played(&[0, 0, 0, 0, 0]));
+        assert_eq!(full.clone().with_history("0.0.0.0.0.0"), full);
+    }
+
+    #[test]
+    fn undo_takes_back_a_pair() {
+        assert_eq!(played(&[3, 3, 2, 4]).undo(), Some(played(&[3, 3])));
+        assert_eq!(played(&[3, 3]).undo(), Some(played(&[])));
+    }
+
+    #[test]
+    fn undo_takes_back_an_unanswered_move() {
+        assert_eq!(played(&[3, 3, 2]).undo(), Some(played(&[3, 3])));
+        assert_eq!(played(&[3]).undo(), Some(played(&[])));
+        assert_eq!(played(&[]).undo(), None);
+    }
+}
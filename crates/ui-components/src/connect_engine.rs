@@ -0,0 +1,329 @@
+// bitboard engine for connect4 boards
+//
+// each column takes height + 1 bits (the extra bit is a sentinel that keeps
+// alignments from wrapping into the next column), bit 0 of a column is the
+// bottom row. a position stores the stones of the player to move and a mask of
+// all stones, so playing a move is just a few bit operations. boards up to
+// 7x8 fit in a u64, wins are always four in a row.
+use std::collections::HashMap;
+
+pub const DEFAULT_DEPTH: u32 = 8;
+pub const MAX_DEPTH: u32 = 12;
+
+const WIN_SCORE: i32 = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    current: u64,
+    mask: u64,
+    moves: u32,
+    width: usize,
+    height: usize,
+}
+
+impl Position {
+    pub fn new(width: usize, height: usize) -> Self {
+        assert!(
+            Position::supports(width, height),
+            "Board is too large for a bitboard"
+        );
+        Position {
+            current: 0,
+            mask: 0,
+            moves: 0,
+            width,
+            height,
+        }
+    }
+
+    pub fn supports(width: usize, height: usize) -> bool {
+        width * (height + 1) <= 64
+    }
+
+    // build a position from the stones of X and O, X always moves first
+    pub fn from_masks(width: usize, height: usize, x: u64, o: u64) -> Self {
+        let mut position = Position::new(width, height);
+        position.mask = x | o;
+        position.moves = position.mask.count_ones();
+        position.current = if position.moves.is_multiple_of(2) {
+            x
+        } else {
+            o
+        };
+        position
+    }
+
+    fn h1(&self) -> usize {
+        self.height + 1
+    }
+
+    // bit for a cell, rows are counted from the top like the html board
+    pub fn cell_bit(&self, row: usize, col: usize) -> u64 {
+        1 << (col * self.h1() + (self.height - 1 - row))
+    }
+
+    fn top_mask(&self, col: usize) -> u64 {
+        1 << (col * self.h1() + self.height - 1)
+    }
+
+    fn bottom_mask(&self, col: usize) -> u64 {
+        1 << (col * self.h1())
+    }
+
+    fn column_mask(&self, col: usize) -> u64 {
+        ((1 << self.height) - 1) << (col * self.h1())
+    }
+
+    fn board_mask(&self) -> u64 {
+        (0..self.width).fold(0, |mask, col| mask | self.column_mask(col))
+    }
+
+    pub fn can_play(&self, col: usize) -> bool {
+        col < self.width && self.mask & self.top_mask(col) == 0
+    }
+
+    pub fn play(&mut self, col: usize) {
+        self.current ^= self.mask;
+        self.mask |= self.mask + self.bottom_mask(col);
+        self.moves += 1;
+    }
+
+    // central columns first, they take part in the most alignments
+    pub fn legal_moves(&self) -> impl Iterator<Item = usize> + '_ {
+        let center = self.width / 2;
+        (0..self.width)
+            .map(move |i| {
+                if i % 2 == 0 {
+                    center + i / 2
+                } else {
+                    center - i.div_ceil(2)
+                }
+            })
+            .filter(|&col| self.can_play(col))
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.moves as usize == self.width * self.height
+    }
+
+    pub fn is_winning_move(&self, col: usize) -> bool {
+        let stones = self.current | ((self.mask + self.bottom_mask(col)) & self.column_mask(col));
+        self.has_alignment(stones)
+    }
+
+    // true if the player who just moved completed four in a row
+    pub fn last_move_won(&self) -> bool {
+        self.has_alignment(self.current ^ self.mask)
+    }
+
+    fn has_alignment(&self, stones: u64) -> bool {
+        let h1 = self.h1();
+        // vertical, horizontal and both diagonals
+        for shift in [1, h1, h1 - 1, h1 + 1] {
+            let pairs = stones & (stones >> shift);
+            if pairs & (pairs >> (2 * shift)) != 0 {
+                return true;
+            }
+        }
+        false
+    }
+
+    // empty cells that would complete four in a row for the given stones
+    fn winning_cells(&self, stones: u64) -> u64 {
+        let h1 = self.h1();
+        // vertical: three stacked stones, the cell above them
+        let mut result = (stones << 1) & (stones << 2) & (stones << 3);
+        for shift in [h1, h1 - 1, h1 + 1] {
+            let pair = (stones << shift) & (stones << (2 * shift));
+            result |= pair & (stones << (3 * shift));
+            result |= pair & (stones >> shift);
+            let pair = (stones >> shift) & (stones >> (2 * shift));
+            result |= pair & (stones << shift);
+            result |= pair & (stones >> (3 * shift));
+        }
+        result & (self.board_mask() ^ self.mask)
+    }
+
+    // static evaluation at the search horizon: difference in open threats
+    fn evaluate(&self) -> i32 {
+        let ours = self.winning_cells(self.current).count_ones() as i32;
+        let theirs = self.winning_cells(self.current ^ self.mask).count_ones() as i32;
+        ours - theirs
+    }
+
+    fn key(&self) -> u64 {
+        self.current + self.mask
+    }
+
+    // best column for the player to move, None if the board is full
+    pub fn best_move(&self, depth: u32) -> Option<usize> {
+        let depth = depth.clamp(1, MAX_DEPTH);
+        if let Some(col) = self.legal_moves().find(|&col| self.is_winning_move(col)) {
+            return Some(col);
+        }
+        let mut search = Search::default();
+        let mut best: Option<(usize, i32)> = None;
+        for col in self.legal_moves() {
+            let mut next = *self;
+            next.play(col);
+            let score = -search.negamax(&next, depth - 1, -WIN_SCORE, WIN_SCORE);
+            if best.is_none_or(|(_, best_score)| score > best_score) {
+                best = Some((col, score));
+            }
+        }
+        best.map(|(col, _)| col)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Bound {
+    Exact,
+    Lower,
+    Upper,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    depth: u32,
+    score: i32,
+    bound: Bound,
+    best: usize,
+}
+
+#[derive(Debug, Default)]
+struct Search {
+    table: HashMap<u64, Entry>,
+}
+
+impl Search {
+    // negamax with alpha-beta pruning and a transposition table, scored for the
+    // player to move; the previous move is assumed not to have won
+    fn negamax(&mut self, pos: &Position, depth: u32, mut alpha: i32, mut beta: i32) -> i32 {
+        if pos.is_full() {
+            return 0;
+        }
+        if pos.legal_moves().any(|col| pos.is_winning_move(col)) {
+            // quicker wins score higher
+            return WIN_SCORE - (pos.moves as i32 + 1);
+        }
+        if depth == 0 {
+            return pos.evaluate();
+        }
+
+        let alpha_orig = alpha;
+        let mut hint = None;
+        if let Some(entry) = self.table.get(&pos.key()) {
+            hint = Some(entry.best);
+            if entry.depth >= depth {
+                match entry.bound {
+                    Bound::Exact => return entry.score,
+                    Bound::Lower => alpha = alpha.max(entry.score),
+                    Bound::Upper => beta = beta.min(entry.score),
+                }
+                if alpha >= beta {
+                    return entry.score;
+                }
+            }
+        }
+
+        // previous best move first, then centre-out
+        let mut order: Vec<usize> = pos.legal_moves().collect();
+        if let Some(best) = hint {
+            if let Some(idx) = order.iter().position(|&col| col == best) {
+                order[..=idx].rotate_right(1);
+            }
+        }
+
+        let mut best_score = -WIN_SCORE;
+        let mut best_move = order[0];
+        for col in order {
+            let mut next = *pos;
+            next.play(col);
+            let score = -self.negamax(&next, depth - 1, -beta, -alpha);
+            if score > best_score {
+                best_score = score;
+                best_move = col;
+            }
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        let bound = if best_score <= alpha_orig {
+            Bound::Upper
+        } else if best_score >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        self.table.insert(
+            pos.key(),
+            Entry {
+                depth,
+                score: best_score,
+                bound,
+                best: best_move,
+            },
+        );
+        best_score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a standard 7x6 board after the given columns were played, X first
+    fn after(moves: &[usize]) -> Position {
+        let mut position = Position::new(7, 6);
+        for &col in moves {
+            assert!(position.can_play(col));
+            position.play(col);
+        }
+        position
+    }
+
+    #[test]
+    fn takes_a_win() {
+        let position = after(&[0, 6, 0, 6, 0, 5]);
+        assert_eq!(position.best_move(DEFAULT_DEPTH), Some(0));
+    }
+
+    #[test]
+    fn wins_rather_than_blocks() {
+        // both players have three stacked, X is to move
+        let position = after(&[0, 6, 0, 6, 0, 6]);
+        assert_eq!(position.best_move(DEFAULT_DEPTH), Some(0));
+    }
+
+    #[test]
+    fn blocks_a_vertical_three() {
+        let position = after(&[0, 1, 0, 1, 0]);
+        assert_eq!(position.best_move(DEFAULT_DEPTH), Some(0));
+    }
+
+    #[test]
+    fn blocks_a_horizontal_three() {
+        let position = after(&[0, 6, 1, 6, 2]);
+        assert_eq!(position.best_move(DEFAULT_DEPTH), Some(3));
+    }
+
+    #[test]
+    fn stops_an_open_three() {
+        // X on 1 and 2 of the bottom row would make 1, 2, 3 with both ends open
+        let position = after(&[1, 6, 2]);
+        let reply = position.best_move(DEFAULT_DEPTH).unwrap();
+        assert!([0, 3, 4].contains(&reply), "played {reply}");
+    }
+
+    #[test]
+    fn finds_the_winning_move() {
+        let position = after(&[0, 6, 1, 6, 2, 5]);
+        assert!(position.is_winning_move(3));
+        assert!(!position.is_winning_move(4));
+        let mut won = position;
+        won.play(3);
+        assert!(won.last_move_won());
+    }
+}
@@ -0,0 +1,123 @@
+// shared interface for the board games
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+pub enum Player {
+    // the human player, always moves first
+    X,
+    // the server
+    O,
+}
+
+impl Player {
+    pub fn opponent(self) -> Player {
+        match self {
+            Player::X => Player::O,
+            Player::O => Player::X,
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Player::X => "X",
+            Player::O => "O",
+        }
+    }
+
+    pub fn from_symbol(symbol: &str) -> Option<Player> {
+        match symbol {
+            "X" => Some(Player::X),
+            "O" => Some(Player::O),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Player {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.symbol())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub enum Outcome {
+    InProgress,
+    Win(Player),
+    Draw,
+}
+
+impl Outcome {
+    pub fn is_over(self) -> bool {
+        self != Outcome::InProgress
+    }
+}
+
+// a game position together with the rules for moving from it
+pub trait Game: Clone + Sized {
+    type Move: Copy + PartialEq + fmt::Debug;
+
+    // the starting position
+    fn new() -> Self;
+
+    // the player whose turn it is
+    fn current_player(&self) -> Player;
+
+    // every move the current player can make, empty once the game is over
+    fn legal_moves(&self) -> Vec<Self::Move>;
+
+    // plays a move for the current player
+    fn apply_move(&mut self, mv: Self::Move) -> Result<(), String>;
+
+    fn outcome(&self) -> Outcome;
+
+    // the board encoding used in links and forms
+    fn encode(&self) -> String;
+
+    fn decode(encoding: &str) -> Result<Self, String>;
+}
+
+pub fn random_move<G: Game>(game: &G) -> Option<G::Move> {
+    game.legal_moves().choose(&mut rand::thread_rng()).copied()
+}
+
+const WIN_SCORE: i32 = 1000;
+
+// exhaustive negamax with alpha-beta pruning, only suitable for small games
+// wins found at a shallower depth score higher so quick wins are preferred
+pub fn best_move<G: Game>(game: &G) -> Option<G::Move> {
+    let mut best: Option<(G::Move, i32)> = None;
+    for mv in game.legal_moves() {
+        let mut next = game.clone();
+        next.apply_move(mv).ok()?;
+        let score = -negamax(&next, 1, -WIN_SCORE, WIN_SCORE);
+        if best.is_none_or(|(_, best_score)| score > best_score) {
+            best = Some((mv, score));
+        }
+    }
+    best.map(|(mv, _)| mv)
+}
+
+// scored for the player about to move
+fn negamax<G: Game>(game: &G, depth: i32, mut alpha: i32, beta: i32) -> i32 {
+    match game.outcome() {
+        Outcome::Win(winner) if winner == game.current_player() => return WIN_SCORE - depth,
+        Outcome::Win(_) => return depth - WIN_SCORE,
+        Outcome::Draw => return 0,
+        Outcome::InProgress => {}
+    }
+    for mv in game.legal_moves() {
+        let mut next = game.clone();
+        if next.apply_move(mv).is_err() {
+            continue;
+        }
+        let score = -negamax(&next, depth + 1, -beta, -alpha);
+        alpha = alpha.max(score);
+        if alpha >= beta {
+            break;
+        }
+    }
+    alpha
+}
@@ -0,0 +1,19 @@
+// for the few pages built with format! instead of dioxus, which escapes the
+// text it renders on its own
+
+// `text` with the characters html gives a meaning replaced by entities, safe
+// in element content and in quoted attribute values
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
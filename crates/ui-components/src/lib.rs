@@ -1,3 +1,12 @@
+pub mod api;
 pub mod battleship;
 pub mod connect;
+pub mod connect_engine;
+pub mod game;
+pub mod html;
+pub mod mnk;
+pub mod record;
+pub mod room;
+pub mod signing;
+pub mod stats;
 pub mod ttt;
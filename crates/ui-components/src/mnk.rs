@@ -0,0 +1,412 @@
+// generalised m,n,k-game board shared by tic-tac-toe and connect4
+//
+// a variant is `rows` x `cols` where the first player to line up `win_length`
+// pieces across, down or diagonally wins. with gravity pieces drop to the
+// lowest empty cell of a column, as in connect4.
+use rand::seq::SliceRandom;
+use std::fmt;
+
+use crate::game::{Outcome, Player};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Variant {
+    pub rows: usize,
+    pub cols: usize,
+    pub win_length: usize,
+    pub gravity: bool,
+}
+
+impl Variant {
+    pub const TIC_TAC_TOE: Variant = Variant::new(3, 3, 3, false);
+    pub const TIC_TAC_TOE_4X4: Variant = Variant::new(4, 4, 4, false);
+    pub const GOMOKU: Variant = Variant::new(15, 15, 5, false);
+    pub const CONNECT_FOUR: Variant = Variant::new(5, 7, 4, true);
+    pub const CONNECT_FOUR_6X7: Variant = Variant::new(6, 7, 4, true);
+
+    // every variant that can be selected by name, the first of each kind is
+    // the default for its page
+    const NAMED: [(&'static str, Variant); 5] = [
+        ("3x3", Variant::TIC_TAC_TOE),
+        ("4x4", Variant::TIC_TAC_TOE_4X4),
+        ("gomoku", Variant::GOMOKU),
+        ("5x7", Variant::CONNECT_FOUR),
+        ("6x7", Variant::CONNECT_FOUR_6X7),
+    ];
+
+    pub const fn new(rows: usize, cols: usize, win_length: usize, gravity: bool) -> Self {
+        Variant {
+            rows,
+            cols,
+            win_length,
+            gravity,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Variant> {
+        Variant::NAMED
+            .iter()
+            .find(|(variant_name, _)| *variant_name == name)
+            .map(|(_, variant)| *variant)
+    }
+
+    pub fn name(&self) -> &'static str {
+        Variant::NAMED
+            .iter()
+            .find(|(_, variant)| variant == self)
+            .map_or("custom", |(name, _)| name)
+    }
+
+    // named variants with or without gravity
+    pub fn all(gravity: bool) -> impl Iterator<Item = Variant> {
+        Variant::NAMED
+            .into_iter()
+            .map(|(_, variant)| variant)
+            .filter(move |variant| variant.gravity == gravity)
+    }
+
+    pub fn cells(&self) -> usize {
+        self.rows * self.cols
+    }
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    // the encoding has the wrong number of cells or rows
+    WrongLength {
+        expected: usize,
+        found: usize,
+    },
+    // a connect4 row has the wrong number of columns
+    WrongRowLength {
+        row: usize,
+        expected: usize,
+        found: usize,
+    },
+    BadSymbol {
+        symbol: String,
+        cell: usize,
+    },
+    // a connect4 piece with an empty cell below it
+    FloatingPiece {
+        row: usize,
+        col: usize,
+    },
+    // X moves first, so X has as many pieces as O or exactly one more
+    ImpossibleCounts {
+        x: usize,
+        o: usize,
+    },
+    BothPlayersWin,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::WrongLength { expected, found } => {
+                write!(f, "expected {expected} cells or rows but found {found}")
+            }
+            ParseError::WrongRowLength {
+                row,
+                expected,
+                found,
+            } => write!(
+                f,
+                "row {row} should have {expected} columns but has {found}"
+            ),
+            ParseError::BadSymbol { symbol, cell } => {
+                write!(
+                    f,
+                    "cell {cell} contains \"{symbol}\", expected X, O or nothing"
+                )
+            }
+            ParseError::FloatingPiece { row, col } => {
+                write!(f, "the piece at row {row}, column {col} is floating")
+            }
+            ParseError::ImpossibleCounts { x, o } => {
+                write!(f, "{x} X pieces and {o} O pieces cannot happen in a game")
+            }
+            ParseError::BothPlayersWin => write!(f, "both players have a winning line"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+// across, down and both diagonals
+const DIRECTIONS: [(isize, isize); 4] = [(0, 1), (1, 0), (1, 1), (1, -1)];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Grid {
+    variant: Variant,
+    cells: Vec<Vec<Option<Player>>>,
+}
+
+impl Grid {
+    pub fn new(variant: Variant) -> Self {
+        Grid {
+            variant,
+            cells: vec![vec![None; variant.cols]; variant.rows],
+        }
+    }
+
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> Option<Player> {
+        self.cells[row][col]
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &Vec<Option<Player>>> {
+        self.cells.iter()
+    }
+
+    // "X", "O" or "" for every cell
+    pub fn symbols(&self) -> Vec<Vec<&'static str>> {
+        self.cells
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|cell| cell.map_or("", Player::symbol))
+                    .collect()
+            })
+            .collect()
+    }
+
+    pub fn count(&self, player: Player) -> usize {
+        self.cells
+            .iter()
+            .flatten()
+            .filter(|&&cell| cell == Some(player))
+            .count()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.cells.iter().flatten().all(Option::is_some)
+    }
+
+    // X moves first, so it is O's turn whenever X has more pieces
+    pub fn current_player(&self) -> Player {
+        if self.count(Player::X) > self.count(Player::O) {
+            Player::O
+        } else {
+            Player::X
+        }
+    }
+
+    // the lowest empty row of a column
+    pub fn landing_row(&self, col: usize) -> Option<usize> {
+        (0..self.variant.rows)
+            .rev()
+            .find(|&row| self.cells[row][col].is_none())
+    }
+
+    pub fn can_place(&self, row: usize, col: usize) -> bool {
+        if row >= self.variant.rows || col >= self.variant.cols {
+            return false;
+        }
+        if self.variant.gravity {
+            self.landing_row(col) == Some(row)
+        } else {
+            self.cells[row][col].is_none()
+        }
+    }
+
+    pub fn place(&mut self, row: usize, col: usize, player: Player) -> Result<(), String> {
+        if !self.can_place(row, col) {
+            return Err("Cannot place player at location".to_string());
+        }
+        self.cells[row][col] = Some(player);
+        Ok(())
+    }
+
+    // every cell a piece can be placed in right now
+    pub fn open_cells(&self) -> Vec<(usize, usize)> {
+        if self.variant.gravity {
+            return (0..self.variant.cols)
+                .filter_map(|col| self.landing_row(col).map(|row| (row, col)))
+                .collect();
+        }
+        let mut cells = vec![];
+        for (i, row) in self.cells.iter().enumerate() {
+            for (j, cell) in row.iter().enumerate() {
+                if cell.is_none() {
+                    cells.push((i, j));
+                }
+            }
+        }
+        cells
+    }
+
+    fn in_bounds(&self, row: isize, col: isize) -> bool {
+        (0..self.variant.rows as isize).contains(&row)
+            && (0..self.variant.cols as isize).contains(&col)
+    }
+
+    // length of the run of `player` pieces through a cell in one direction,
+    // counting the cell itself as if it held a `player` piece
+    fn run_length(
+        &self,
+        row: usize,
+        col: usize,
+        (dx, dy): (isize, isize),
+        player: Player,
+    ) -> usize {
+        let mut length = 1;
+        for sign in [1, -1] {
+            let (mut r, mut c) = (row as isize + sign * dx, col as isize + sign * dy);
+            while self.in_bounds(r, c) && self.cells[r as usize][c as usize] == Some(player) {
+                length += 1;
+                r += sign * dx;
+                c += sign * dy;
+            }
+        }
+        length
+    }
+
+    // builds a grid from one symbol per cell in row-major order, checking that
+    // the position could have come up in a real game
+    pub fn from_symbols<'a>(
+        variant: Variant,
+        symbols: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Self, ParseError> {
+        let mut grid = Grid::new(variant);
+        for (cell, symbol) in symbols.into_iter().enumerate() {
+            let player = match symbol {
+                "" => None,
+                symbol => Some(Player::from_symbol(symbol).ok_or(ParseError::BadSymbol {
+                    symbol: symbol.to_owned(),
+                    cell,
+                })?),
+            };
+            grid.cells[cell / variant.cols][cell % variant.cols] = player;
+        }
+        grid.validate()?;
+        Ok(grid)
+    }
+
+    fn validate(&self) -> Result<(), ParseError> {
+        let (x, o) = (self.count(Player::X), self.count(Player::O));
+        if x != o && x != o + 1 {
+            return Err(ParseError::ImpossibleCounts { x, o });
+        }
+        if self.variant.gravity {
+            for row in 0..self.variant.rows - 1 {
+                for col in 0..self.variant.cols {
+                    if self.cells[row][col].is_some() && self.cells[row + 1][col].is_none() {
+                        return Err(ParseError::FloatingPiece { row, col });
+                    }
+                }
+            }
+        }
+        if self.wins(Player::X) && self.wins(Player::O) {
+            return Err(ParseError::BothPlayersWin);
+        }
+        Ok(())
+    }
+
+    pub fn wins(&self, player: Player) -> bool {
+        self.cells.iter().enumerate().any(|(i, row)| {
+            row.iter().enumerate().any(|(j, &cell)| {
+                cell == Some(player)
+                    && DIRECTIONS.iter().any(|&direction| {
+                        self.run_length(i, j, direction, player) >= self.variant.win_length
+                    })
+            })
+        })
+    }
+
+    pub fn winner(&self) -> Option<Player> {
+        for (i, row) in self.cells.iter().enumerate() {
+            for (j, cell) in row.iter().enumerate() {
+                let Some(player) = *cell else {
+                    continue;
+                };
+                for direction in DIRECTIONS {
+                    if self.run_length(i, j, direction, player) >= self.variant.win_length {
+                        return Some(player);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    pub fn outcome(&self) -> Outcome {
+        match self.winner() {
+            Some(player) => Outcome::Win(player),
+            None if self.is_full() => Outcome::Draw,
+            None => Outcome::InProgress,
+        }
+    }
+
+    fn is_winning_cell(&self, row: usize, col: usize, player: Player) -> bool {
+        DIRECTIONS.iter().any(|&direction| {
+            self.run_length(row, col, direction, player) >= self.variant.win_length
+        })
+    }
+
+    // cells worth considering on large boards: open cells next to a piece
+    fn candidate_cells(&self) -> Vec<(usize, usize)> {
+        let open = self.open_cells();
+        if self.variant.gravity || self.cells.iter().flatten().all(Option::is_none) {
+            return open;
+        }
+        let near: Vec<(usize, usize)> = open
+            .iter()
+            .copied()
+            .filter(|&(i, j)| {
+                (-2..=2).any(|dx: isize| {
+                    (-2..=2).any(|dy: isize| {
+                        let (r, c) = (i as isize + dx, j as isize + dy);
+                        self.in_bounds(r, c) && self.cells[r as usize][c as usize].is_some()
+                    })
+                })
+            })
+            .collect();
+        if near.is_empty() {
+            open
+        } else {
+            near
+        }
+    }
+
+    // one-ply heuristic for boards too large to search: win if possible, block
+    // an immediate loss, otherwise extend the longest lines for either side
+    pub fn heuristic_move(&self, player: Player) -> Option<(usize, usize)> {
+        let candidates = self.candidate_cells();
+        let opponent = player.opponent();
+        for target in [player, opponent] {
+            if let Some(&cell) = candidates
+                .iter()
+                .find(|&&(i, j)| self.is_winning_cell(i, j, target))
+            {
+                return Some(cell);
+            }
+        }
+        let score = |&(i, j): &(usize, usize)| -> usize {
+            DIRECTIONS
+                .iter()
+                .map(|&direction| {
+                    let ours = self.run_length(i, j, direction, player);
+                    let theirs = self.run_length(i, j, direction, opponent);
+                    // attacking is worth slightly more than defending
+                    2 * ours * ours + theirs * theirs
+                })
+                .sum()
+        };
+        let best = candidates.iter().map(score).max()?;
+        let best_cells: Vec<(usize, usize)> = candidates
+            .iter()
+            .copied()
+            .filter(|cell| score(cell) == best)
+            .collect();
+        best_cells.choose(&mut rand::thread_rng()).copied()
+    }
+}
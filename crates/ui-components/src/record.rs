@@ -0,0 +1,496 @@
+// portable text records of played games
+//
+// a record is a list of tags followed by the moves, in the spirit of PGN:
+//
+//     [Game "ttt"]
+//     [Player "alice"]
+//     [Date "2026-10-18"]
+//     [Variant "3x3"]
+//     [Result "1-0"]
+//
+//     1. b2 a1 2. c3 a3 3. a2 c1 4. c2 1-0
+//
+// tag values are quoted, with \" and \\ escaped. Game, Player, Date, Variant
+// and Result are always present, games may add their own tags after them.
+// moves are separated by whitespace, the numbers before each X move are only
+// there for reading and are skipped by the parser. the move text ends with
+// the result: "1-0" when X (the player) won, "0-1" when O won, "1/2-1/2" for
+// a draw and "*" for an unfinished game.
+//
+// cells are written as a column letter and a row number counted from the top,
+// so "a1" is the top left corner. connect4 moves are just the column number.
+use dioxus::prelude::*;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::game::{Outcome, Player};
+use crate::{battleship, connect, ttt};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub game: String,
+    pub player: String,
+    pub date: String,
+    pub variant: String,
+    pub result: Outcome,
+    // game specific tags, in order
+    pub extra: Vec<(String, String)>,
+    pub moves: Vec<String>,
+}
+
+impl Record {
+    pub fn new(game: &str, player: &str, variant: &str, result: Outcome) -> Self {
+        Record {
+            game: game.to_owned(),
+            player: player.to_owned(),
+            date: chrono::Utc::now().format("%Y-%m-%d").to_string(),
+            variant: variant.to_owned(),
+            result,
+            extra: vec![],
+            moves: vec![],
+        }
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.extra
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn file_name(&self) -> String {
+        format!("{}-{}.txt", self.game, self.date)
+    }
+
+    // the whole record as a data url, for download links
+    pub fn data_url(&self) -> String {
+        format!(
+            "data:text/plain;charset=utf-8,{}",
+            percent_encode(&self.to_string())
+        )
+    }
+
+    // a link that saves the record as a file, for the raw html pages
+    pub fn download_link(&self) -> String {
+        format!(
+            r#"<a download="{}" href="{}">Download game</a>"#,
+            self.file_name(),
+            self.data_url()
+        )
+    }
+}
+
+fn result_token(result: Outcome) -> &'static str {
+    match result {
+        Outcome::Win(Player::X) => "1-0",
+        Outcome::Win(Player::O) => "0-1",
+        Outcome::Draw => "1/2-1/2",
+        Outcome::InProgress => "*",
+    }
+}
+
+fn parse_result(token: &str) -> Option<Outcome> {
+    match token {
+        "1-0" => Some(Outcome::Win(Player::X)),
+        "0-1" => Some(Outcome::Win(Player::O)),
+        "1/2-1/2" => Some(Outcome::Draw),
+        "*" => Some(Outcome::InProgress),
+        _ => None,
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let tags = [
+            ("Game", self.game.as_str()),
+            ("Player", self.player.as_str()),
+            ("Date", self.date.as_str()),
+            ("Variant", self.variant.as_str()),
+            ("Result", result_token(self.result)),
+        ];
+        let extra = self
+            .extra
+            .iter()
+            .map(|(tag, value)| (tag.as_str(), value.as_str()));
+        for (tag, value) in tags.into_iter().chain(extra) {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+            writeln!(f, "[{tag} \"{value}\"]")?;
+        }
+        writeln!(f)?;
+        // at most five numbered pairs per line
+        for (n, pair) in self.moves.chunks(2).enumerate() {
+            let separator = if n > 0 && n % 5 == 0 {
+                "\n"
+            } else if n > 0 {
+                " "
+            } else {
+                ""
+            };
+            write!(f, "{separator}{}. {}", n + 1, pair.join(" "))?;
+        }
+        let separator = if self.moves.is_empty() { "" } else { " " };
+        writeln!(f, "{separator}{}", result_token(self.result))
+    }
+}
+
+// [Name "value"] with escapes in the value
+fn parse_tag(line: &str) -> Option<(String, String)> {
+    let inner = line.strip_prefix('[')?.strip_suffix(']')?;
+    let (name, value) = inner.split_once(' ')?;
+    let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+    let mut unescaped = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.push(chars.next()?),
+            '"' => return None,
+            c => unescaped.push(c),
+        }
+    }
+    Some((name.to_owned(), unescaped))
+}
+
+impl FromStr for Record {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut tags = vec![];
+        let mut lines = text.lines().map(str::trim).peekable();
+        while let Some(line) = lines.next_if(|line| line.is_empty() || line.starts_with('[')) {
+            if !line.is_empty() {
+                tags.push(parse_tag(line).ok_or(format!("Invalid tag line: {line}"))?);
+            }
+        }
+        let mut take = |name: &str| -> Result<String, String> {
+            let idx = tags
+                .iter()
+                .position(|(tag, _)| tag == name)
+                .ok_or(format!("Missing {name} tag"))?;
+            Ok(tags.remove(idx).1)
+        };
+        let game = take("Game")?;
+        let player = take("Player")?;
+        let date = take("Date")?;
+        // the date ends up in the name of the downloaded file
+        if chrono::NaiveDate::parse_from_str(&date, "%Y-%m-%d").is_err() {
+            return Err(format!("Invalid date \"{date}\", expected YYYY-MM-DD"));
+        }
+        let variant = take("Variant")?;
+        let result = take("Result")?;
+        let result = parse_result(&result).ok_or(format!("Invalid result \"{result}\""))?;
+
+        let mut moves: Vec<String> = vec![];
+        let mut ended = false;
+        for token in lines.flat_map(str::split_whitespace) {
+            if ended {
+                return Err(format!("Unexpected \"{token}\" after the result"));
+            }
+            if let Some(end) = parse_result(token) {
+                if end != result {
+                    return Err(format!(
+                        "The move text ends in {token} but the Result tag is {}",
+                        result_token(result)
+                    ));
+                }
+                ended = true;
+            } else if token
+                .strip_suffix('.')
+                .is_some_and(|number| number.parse::<usize>().is_ok())
+            {
+                continue;
+            } else {
+                moves.push(token.to_owned());
+            }
+        }
+        if !ended {
+            return Err("The move text does not end with a result".to_owned());
+        }
+        Ok(Record {
+            game,
+            player,
+            date,
+            variant,
+            result,
+            extra: tags,
+            moves,
+        })
+    }
+}
+
+// "b3" for row 2, column 1
+pub fn square(row: usize, col: usize) -> String {
+    let letter = (b'a' + col as u8) as char;
+    format!("{letter}{}", row + 1)
+}
+
+pub fn parse_square(square: &str) -> Option<(usize, usize)> {
+    let mut chars = square.chars();
+    let letter = chars.next().filter(char::is_ascii_lowercase)?;
+    let row: usize = chars.as_str().parse().ok()?;
+    Some((row.checked_sub(1)?, (letter as u8 - b'a') as usize))
+}
+
+pub(crate) fn percent_encode(text: &str) -> String {
+    let mut encoded = String::new();
+    for byte in text.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-_.~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    encoded
+}
+
+// one board drawn in a replay, rows of cell symbols
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagram {
+    pub title: &'static str,
+    pub cells: Vec<Vec<&'static str>>,
+}
+
+// every position of a replayed game, from the start to after the last move
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replay {
+    pub positions: Vec<Vec<Diagram>>,
+    pub outcome: Outcome,
+}
+
+fn replay(record: &Record) -> Result<Replay, String> {
+    let replay = match record.game.as_str() {
+        "ttt" => ttt::replay(record)?,
+        "connect" => connect::replay(record)?,
+        "battleship" => battleship::replay(record)?,
+        game => return Err(format!("Unknown game \"{game}\"")),
+    };
+    if record.result != Outcome::InProgress && record.result != replay.outcome {
+        return Err(format!(
+            "The record says {} but the moves end in {}",
+            result_token(record.result),
+            result_token(replay.outcome)
+        ));
+    }
+    Ok(replay)
+}
+
+#[derive(Debug, Clone, PartialEq, Props)]
+struct ReplayProps {
+    text: String,
+    record: Record,
+    ply: usize,
+    diagrams: Vec<Diagram>,
+}
+
+#[component]
+fn Upload(cx: Scope) -> Element {
+    cx.render(rsx! {
+        body {
+            form {
+                action: "/record.php",
+                method: "POST",
+                enctype: "multipart/form-data",
+                label { r#for: "file", "Game record file:" }
+                input { id: "file", name: "file", r#type: "file", accept: ".txt,text/plain" }
+                label { r#for: "record", "or paste it here:" }
+                textarea { id: "record", name: "record", rows: "12", cols: "60" }
+                input { r#type: "submit", value: "Replay" }
+            }
+        }
+    })
+}
+
+#[component]
+fn ReplayView(cx: Scope<ReplayProps>) -> Element {
+    let record = &cx.props.record;
+    let ply = cx.props.ply;
+    let last = record.moves.len();
+    let result = result_token(record.result);
+    let steps = [
+        ("First", 0),
+        ("Previous", ply.saturating_sub(1)),
+        ("Next", (ply + 1).min(last)),
+        ("Last", last),
+    ];
+    cx.render(rsx! {
+        body {
+            p { "{record.game} ({record.variant}), {record.player}, {record.date}, result {result}" }
+            p { "Move {ply} of {last}" }
+            cx.props.diagrams.iter().map(|diagram| {
+                rsx! {
+                    h3 { "{diagram.title}" }
+                    table {
+                        tbody {
+                            diagram.cells.iter().map(|row| {
+                                rsx! {
+                                    tr {
+                                        row.iter().map(|cell| {
+                                            rsx! {
+                                                td {
+                                                    width: "50px",
+                                                    height: "50px",
+                                                    border: "1px solid black",
+                                                    text_align: "center",
+                                                    "{cell}"
+                                                }
+                                            }
+                                        })
+                                    }
+                                }
+                            })
+                        }
+                    }
+                }
+            })
+            ol {
+                record.moves.iter().enumerate().map(|(n, mv)| {
+                    rsx! {
+                        li {
+                            if n < ply {
+                                rsx! { b { "{mv}" } }
+                            } else {
+                                rsx! { "{mv}" }
+                            }
+                        }
+                    }
+                })
+            }
+            steps.into_iter().map(|(label, target)| {
+                rsx! {
+                    form {
+                        action: "/record.php",
+                        method: "POST",
+                        display: "inline",
+                        // text content is escaped, attribute values are not
+                        textarea { name: "record", hidden: true, "{cx.props.text}" }
+                        input { r#type: "hidden", name: "ply", value: "{target}" }
+                        input { r#type: "submit", value: "{label}" }
+                    }
+                }
+            })
+            // the game and date in the file name were checked by the parser
+            // and the replay
+            a { download: "{record.file_name()}", href: "{record.data_url()}", "Download game" }
+        }
+    })
+}
+
+pub fn get_form_html() -> String {
+    let mut app = VirtualDom::new(Upload);
+    let _ = app.rebuild();
+    format!(
+        "<!DOCTYPE html><html lang='en'>{}</html",
+        dioxus_ssr::render(&app)
+    )
+}
+
+// the position after `ply` moves of an uploaded record, the final position
+// when no ply is given
+pub fn replay_html(text: &str, ply: Option<usize>) -> Result<String, String> {
+    let record: Record = text.parse()?;
+    let mut replay = replay(&record)?;
+    let ply = ply.unwrap_or(record.moves.len()).min(record.moves.len());
+    let mut app = VirtualDom::new_with_props(
+        ReplayView,
+        ReplayProps {
+            text: text.to_owned(),
+            diagrams: replay.positions.swap_remove(ply),
+            record,
+            ply,
+        },
+    );
+    let _ = app.rebuild();
+    Ok(format!(
+        "<!DOCTYPE html><html lang='en'>{}</html",
+        dioxus_ssr::render(&app)
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Record {
+        Record {
+            game: "ttt".to_owned(),
+            player: "alice \"the\" \\best\\".to_owned(),
+            date: "2026-10-18".to_owned(),
+            variant: "3x3".to_owned(),
+            result: Outcome::Win(Player::X),
+            extra: vec![("Difficulty".to_owned(), "easy".to_owned())],
+            moves: ["b2", "a1", "c3", "a3", "a2", "c1", "c2"]
+                .map(str::to_owned)
+                .to_vec(),
+        }
+    }
+
+    #[test]
+    fn display_then_parse_is_the_same_record() {
+        let record = sample();
+        assert_eq!(record.to_string().parse::<Record>().unwrap(), record);
+
+        let mut long = sample();
+        long.game = "connect".to_owned();
+        long.result = Outcome::InProgress;
+        long.moves = (0..23).map(|n| (n % 7 + 1).to_string()).collect();
+        assert_eq!(long.to_string().parse::<Record>().unwrap(), long);
+    }
+
+    #[test]
+    fn display_format() {
+        let text = sample().to_string();
+        assert_eq!(
+            text,
+            "[Game \"ttt\"]\n\
+             [Player \"alice \\\"the\\\" \\\\best\\\\\"]\n\
+             [Date \"2026-10-18\"]\n\
+             [Variant \"3x3\"]\n\
+             [Result \"1-0\"]\n\
+             [Difficulty \"easy\"]\n\
+             \n\
+             1. b2 a1 2. c3 a3 3. a2 c1 4. c2 1-0\n"
+        );
+    }
+
+    #[test]
+    fn parses_loose_move_text() {
+        let text = "[Game \"ttt\"]\n[Player \"bob\"]\n[Date \"2026-01-02\"]\n\
+                    [Variant \"3x3\"]\n[Result \"1/2-1/2\"]\n\n  b2   a1\n\nc3 1/2-1/2\n";
+        let record: Record = text.parse().unwrap();
+        assert_eq!(record.moves, ["b2", "a1", "c3"]);
+        assert_eq!(record.result, Outcome::Draw);
+        assert!(record.extra.is_empty());
+    }
+
+    #[test]
+    fn rejects_bad_records() {
+        let good = sample().to_string();
+        for (from, to, error) in [
+            ("[Player", "[Name", "Missing Player tag"),
+            (
+                "2026-10-18",
+                "x\\\" onmouseover=\\\"alert(3)",
+                "Invalid date",
+            ),
+            ("2026-10-18", "2026-13-01", "Invalid date"),
+            ("[Result \"1-0\"]", "[Result \"2-0\"]", "Invalid result"),
+            ("c2 1-0", "c2 0-1", "ends in 0-1"),
+            ("c2 1-0", "c2", "does not end with a result"),
+            ("c2 1-0", "c2 1-0 d4", "after the result"),
+            ("[Game \"ttt\"]", "[Game ttt]", "Invalid tag line"),
+        ] {
+            let text = good.replacen(from, to, 1);
+            let err = text.parse::<Record>().unwrap_err();
+            assert!(err.contains(error), "{to:?}: {err}");
+        }
+    }
+
+    #[test]
+    fn squares() {
+        assert_eq!(square(0, 0), "a1");
+        assert_eq!(square(2, 1), "b3");
+        assert_eq!(parse_square("b3"), Some((2, 1)));
+        assert_eq!(parse_square("a0"), None);
+        assert_eq!(parse_square("B3"), None);
+        assert_eq!(parse_square("3"), None);
+    }
+}
@@ -0,0 +1,698 @@
+// game rooms where two people play each other instead of the server
+//
+// the player who creates a room takes seat 0 and moves first (X), the player
+// who joins with the invite code takes seat 1 (O). seats are tied to an opaque
+// token the server keeps in each player's session. anyone else can follow the
+// game from the read-only watch page, which never shows hidden ships.
+use dioxus::prelude::*;
+use serde::Serialize;
+
+use crate::battleship::{self, Coordinate, FleetOptions, Hit, Miss, Mode, Ship, Untried};
+use crate::game::{Game, Outcome, Player};
+use crate::stats::{GameResult, PlayerResult};
+use crate::{connect, ttt};
+
+// every game a room can be created for, as "game:variant"
+pub const GAMES: [(&str, &str); 6] = [
+    ("ttt:3x3", "Tic-tac-toe 3x3"),
+    ("ttt:4x4", "Tic-tac-toe 4x4"),
+    ("ttt:gomoku", "Gomoku (15x15)"),
+    ("connect:5x7", "Connect-4 5x7"),
+    ("connect:6x7", "Connect-4 6x7"),
+    ("battleship", "Battleship"),
+];
+
+#[derive(Debug, Clone)]
+enum RoomGame {
+    Ttt(ttt::Board),
+    Connect(connect::Board),
+    // the fleet of each seat, fired at by the other seat
+    Battleship(Box<[battleship::Board; 2]>),
+}
+
+// what changed in a room, pushed to everyone watching it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RoomUpdate {
+    // "state" when a watcher connects, then "joined", "move", "game_over" or
+    // "watchers" when someone starts or stops watching
+    pub event: &'static str,
+    // the seat that joined or moved
+    pub seat: Option<usize>,
+    pub r#move: Option<String>,
+    pub turn: Option<usize>,
+    pub outcome: Outcome,
+    // every board as an outsider sees it, battleship ships stay hidden
+    pub boards: Vec<Vec<Vec<&'static str>>>,
+    pub watchers: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Seat {
+    pub token: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Room {
+    game: RoomGame,
+    pub seats: [Option<Seat>; 2],
+}
+
+fn player_seat(player: Player) -> usize {
+    match player {
+        Player::X => 0,
+        Player::O => 1,
+    }
+}
+
+impl Room {
+    // `game` is one of the keys of GAMES
+    pub fn new(game: &str) -> Result<Self, String> {
+        let game = match game.split_once(':').unwrap_or((game, "")) {
+            ("ttt", variant) => RoomGame::Ttt(ttt::Board::with_variant(
+                ttt::variant_from_name(variant).ok_or(format!("Unknown variant \"{variant}\""))?,
+            )),
+            ("connect", variant) => RoomGame::Connect(connect::Board::with_variant(
+                connect::variant_from_name(variant)
+                    .ok_or(format!("Unknown variant \"{variant}\""))?,
+            )),
+            ("battleship", _) => {
+                let options = FleetOptions::default();
+                RoomGame::Battleship(Box::new([
+                    battleship::Board::two_sided(&options)?,
+                    battleship::Board::two_sided(&options)?,
+                ]))
+            }
+            (game, _) => return Err(format!("Unknown game \"{game}\"")),
+        };
+        Ok(Room {
+            game,
+            seats: [None, None],
+        })
+    }
+
+    // the key of GAMES without the variant
+    pub fn game(&self) -> &'static str {
+        match &self.game {
+            RoomGame::Ttt(_) => "ttt",
+            RoomGame::Connect(_) => "connect",
+            RoomGame::Battleship(_) => "battleship",
+        }
+    }
+
+    pub fn title(&self) -> String {
+        match &self.game {
+            RoomGame::Ttt(board) => format!("Tic-tac-toe {}", board.grid().variant()),
+            RoomGame::Connect(board) => format!("Connect-4 {}", board.grid().variant()),
+            RoomGame::Battleship(_) => "Battleship".to_owned(),
+        }
+    }
+
+    pub fn seat_of(&self, token: &str) -> Option<usize> {
+        self.seats
+            .iter()
+            .position(|seat| seat.as_ref().is_some_and(|seat| seat.token == token))
+    }
+
+    fn name(&self, seat: usize) -> &str {
+        self.seats[seat]
+            .as_ref()
+            .map_or("", |seat| seat.name.as_str())
+    }
+
+    // takes the first free seat, a player who is already seated keeps theirs
+    pub fn join(&mut self, token: &str, name: &str) -> Result<usize, String> {
+        if let Some(seat) = self.seat_of(token) {
+            return Ok(seat);
+        }
+        let seat = self
+            .seats
+            .iter()
+            .position(Option::is_none)
+            .ok_or("The room is full".to_string())?;
+        self.seats[seat] = Some(Seat {
+            token: token.to_owned(),
+            name: name.to_owned(),
+        });
+        Ok(seat)
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.seats.iter().all(Option::is_some)
+    }
+
+    // seat 0 is X
+    pub fn outcome(&self) -> Outcome {
+        match &self.game {
+            RoomGame::Ttt(board) => board.outcome(),
+            RoomGame::Connect(board) => board.outcome(),
+            RoomGame::Battleship(boards) => {
+                if boards[1].outcome().is_over() {
+                    Outcome::Win(Player::X)
+                } else if boards[0].outcome().is_over() {
+                    Outcome::Win(Player::O)
+                } else {
+                    Outcome::InProgress
+                }
+            }
+        }
+    }
+
+    // the seat to move, None until both players are in or once the game is over
+    pub fn turn(&self) -> Option<usize> {
+        if !self.is_full() || self.outcome().is_over() {
+            return None;
+        }
+        Some(match &self.game {
+            RoomGame::Ttt(board) => player_seat(board.current_player()),
+            RoomGame::Connect(board) => player_seat(board.current_player()),
+            // seat 0 has fired as often as seat 1 whenever it is seat 0's turn
+            RoomGame::Battleship(boards) => {
+                usize::from(boards[1].shots.len() > boards[0].shots.len())
+            }
+        })
+    }
+
+    // plays a move for the player with this token, moves are "row,col" for
+    // tic-tac-toe and battleship and a column for connect4
+    pub fn play(&mut self, token: &str, mv: &str) -> Result<(), String> {
+        let seat = self
+            .seat_of(token)
+            .ok_or("You are not playing in this room")?;
+        match self.turn() {
+            None if !self.is_full() => return Err("Waiting for an opponent".to_string()),
+            None => return Err("Game is over".to_string()),
+            Some(turn) if turn != seat => return Err("It is not your turn".to_string()),
+            Some(_) => {}
+        }
+        match &mut self.game {
+            RoomGame::Ttt(board) => {
+                let variant = board.grid().variant();
+                let target = Coordinate::parse(mv, variant.rows, variant.cols)?;
+                board.apply_move(target.into())
+            }
+            RoomGame::Connect(board) => {
+                let col = mv
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("\"{mv}\" is not a column"))?;
+                board.apply_move(col)
+            }
+            RoomGame::Battleship(boards) => {
+                let target = Coordinate::parse(mv, battleship::ROWS, battleship::COLS)?;
+                boards[1 - seat].apply_move(target.into())
+            }
+        }
+    }
+
+    // one result for each seat once the game is over
+    pub fn results(&self, seconds: Option<i64>) -> Vec<GameResult> {
+        let (variant, moves) = match &self.game {
+            RoomGame::Ttt(board) => (board.grid().variant().name(), board.move_count()),
+            RoomGame::Connect(board) => (board.grid().variant().name(), board.move_count()),
+            RoomGame::Battleship(boards) => (
+                Mode::TwoSided.name(),
+                boards[0].shots.len() + boards[1].shots.len(),
+            ),
+        };
+        let outcome = self.outcome();
+        let finished = chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
+        (0..2)
+            .filter_map(|seat| {
+                Some(GameResult {
+                    player: self.seats[seat].as_ref()?.name.clone(),
+                    game: self.game().to_owned(),
+                    variant: variant.to_owned(),
+                    result: PlayerResult::for_player(outcome, [Player::X, Player::O][seat])?,
+                    moves,
+                    seconds,
+                    finished: finished.clone(),
+                })
+            })
+            .collect()
+    }
+
+    pub fn update(
+        &self,
+        event: &'static str,
+        seat: Option<usize>,
+        mv: Option<&str>,
+        watchers: usize,
+    ) -> RoomUpdate {
+        let boards = self
+            .grids(None)
+            .into_iter()
+            .map(|grid| {
+                grid.cells
+                    .into_iter()
+                    .map(|row| row.into_iter().map(|(symbol, _)| symbol).collect())
+                    .collect()
+            })
+            .collect();
+        RoomUpdate {
+            event,
+            seat,
+            r#move: mv.map(str::to_owned),
+            turn: self.turn(),
+            outcome: self.outcome(),
+            boards,
+            watchers,
+        }
+    }
+
+    // the boards as `viewer` sees them, moves are offered when it is their turn
+    fn grids(&self, viewer: Option<usize>) -> Vec<RoomGrid> {
+        let can_move = viewer.is_some() && self.turn() == viewer;
+        match &self.game {
+            RoomGame::Ttt(board) => {
+                let grid = board.grid();
+                let cells = grid
+                    .symbols()
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, row)| {
+                        row.into_iter()
+                            .enumerate()
+                            .map(|(j, symbol)| {
+                                let mv =
+                                    (can_move && grid.can_place(i, j)).then(|| format!("{i},{j}"));
+                                (symbol, mv)
+                            })
+                            .collect()
+                    })
+                    .collect();
+                vec![RoomGrid {
+                    title: "Board".to_owned(),
+                    cells,
+                }]
+            }
+            RoomGame::Connect(board) => {
+                let grid = board.grid();
+                let cells = grid
+                    .symbols()
+                    .into_iter()
+                    .map(|row| {
+                        row.into_iter()
+                            .enumerate()
+                            .map(|(j, symbol)| {
+                                // any cell of a column plays that column
+                                let open = symbol.is_empty() && grid.landing_row(j).is_some();
+                                (symbol, (can_move && open).then(|| j.to_string()))
+                            })
+                            .collect()
+                    })
+                    .collect();
+                vec![RoomGrid {
+                    title: "Board".to_owned(),
+                    cells,
+                }]
+            }
+            RoomGame::Battleship(boards) => {
+                // spectators see both fleets from seat 0's side
+                let seat = viewer.unwrap_or(0);
+                let over = self.outcome().is_over();
+                let waters = |board: &battleship::Board, own: bool, can_move: bool| {
+                    board
+                        .tiles
+                        .iter()
+                        .enumerate()
+                        .map(|(i, row)| {
+                            row.iter()
+                                .enumerate()
+                                .map(|(j, tile)| match tile {
+                                    Hit => ("X", None),
+                                    Miss => ("O", None),
+                                    // ships stay hidden from the other side
+                                    // until the game is over
+                                    Ship if own || over => ("#", None),
+                                    Untried | Ship => ("", can_move.then(|| format!("{i},{j}"))),
+                                })
+                                .collect()
+                        })
+                        .collect()
+                };
+                let own = viewer.is_some();
+                let (enemy_title, own_title) = if own {
+                    ("Enemy waters".to_owned(), "Your fleet".to_owned())
+                } else {
+                    (
+                        format!("{}'s fleet", self.name(1)),
+                        format!("{}'s fleet", self.name(0)),
+                    )
+                };
+                vec![
+                    RoomGrid {
+                        title: enemy_title,
+                        cells: waters(&boards[1 - seat], false, can_move),
+                    },
+                    RoomGrid {
+                        title: own_title,
+                        cells: waters(&boards[seat], own, false),
+                    },
+                ]
+            }
+        }
+    }
+
+    fn status(&self, viewer: Option<usize>) -> String {
+        if !self.is_full() {
+            return "Waiting for an opponent to join".to_owned();
+        }
+        match (self.outcome(), self.turn()) {
+            (Outcome::Win(player), _) => {
+                let winner = player_seat(player);
+                match viewer {
+                    Some(seat) if seat == winner => "You won!".to_owned(),
+                    Some(_) => "You lost!".to_owned(),
+                    None => format!("{} won!", self.name(winner)),
+                }
+            }
+            (Outcome::Draw, _) => "Draw".to_owned(),
+            (Outcome::InProgress, Some(turn)) if Some(turn) == viewer => "Your turn".to_owned(),
+            (Outcome::InProgress, Some(turn)) => format!("Waiting for {}", self.name(turn)),
+            (Outcome::InProgress, None) => "".to_owned(),
+        }
+    }
+
+    // the room page for the player in seat `viewer`, or for someone who is
+    // not seated. `message` explains a rejected move
+    pub fn html(
+        &self,
+        code: &str,
+        viewer: Option<usize>,
+        message: &str,
+        watchers: usize,
+    ) -> String {
+        self.page(
+            code,
+            viewer,
+            message,
+            watchers,
+            viewer.is_none() && !self.is_full(),
+        )
+    }
+
+    // the read-only page for spectators
+    pub fn watch_html(&self, code: &str, watchers: usize) -> String {
+        self.page(code, None, "", watchers, false)
+    }
+
+    fn page(
+        &self,
+        code: &str,
+        viewer: Option<usize>,
+        message: &str,
+        watchers: usize,
+        can_join: bool,
+    ) -> String {
+        let players: Vec<String> = (0..2)
+            .map(|seat| match &self.seats[seat] {
+                Some(player) => format!("{} ({})", player.name, [Player::X, Player::O][seat]),
+                None => "(open seat)".to_owned(),
+            })
+            .collect();
+        let mut app = VirtualDom::new_with_props(
+            RoomPage,
+            RoomPageProps {
+                code: code.to_owned(),
+                title: self.title(),
+                players,
+                status: self.status(viewer),
+                message: message.to_owned(),
+                grids: self.grids(viewer),
+                // poll until the game is over unless it is our move
+                refresh: !self.outcome().is_over()
+                    && (self.turn().is_none() || self.turn() != viewer),
+                can_join,
+                watchers,
+            },
+        );
+        let _ = app.rebuild();
+        format!(
+            "<!DOCTYPE html><html lang='en'>{}</html",
+            dioxus_ssr::render(&app)
+        )
+    }
+}
+
+// one board on the room page, each cell is a symbol and the move it plays
+#[derive(Debug, Clone, PartialEq)]
+struct RoomGrid {
+    title: String,
+    cells: Vec<Vec<(&'static str, Option<String>)>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Props)]
+struct RoomPageProps {
+    code: String,
+    title: String,
+    players: Vec<String>,
+    status: String,
+    message: String,
+    grids: Vec<RoomGrid>,
+    refresh: bool,
+    can_join: bool,
+    watchers: usize,
+}
+
+#[component]
+fn Lobby(cx: Scope) -> Element {
+    cx.render(rsx! {
+        body {
+            h3 { "Create a room" }
+            form {
+                action: "/room.php",
+                method: "POST",
+                label { r#for: "name", "Name: " }
+                input { id: "name", name: "name", r#type: "text", required: true }
+                label { r#for: "game", "Game: " }
+                select {
+                    id: "game",
+                    name: "game",
+                    GAMES.iter().map(|(value, label)| rsx! { option { value: "{value}", "{label}" } })
+                }
+                input { r#type: "submit", value: "Create" }
+            }
+            h3 { "Join a room" }
+            form {
+                action: "/room.php/join",
+                method: "GET",
+                label { r#for: "code", "Invite code: " }
+                input { id: "code", name: "code", r#type: "text", required: true }
+                input { r#type: "submit", value: "Join" }
+            }
+        }
+    })
+}
+
+#[component]
+fn RoomPage(cx: Scope<RoomPageProps>) -> Element {
+    let code = &cx.props.code;
+    cx.render(rsx! {
+        // browsers with javascript get pushed updates, the rest poll
+        if cx.props.refresh {
+            rsx! { head { noscript { meta { http_equiv: "refresh", content: "2" } } } }
+        }
+        script { dangerous_inner_html: "{live_updates(code, cx.props.refresh)}" }
+        body {
+            h3 { "{cx.props.title}" }
+            p { "Invite code: " a { href: "/room.php/{code}", "{code}" } }
+            p { "Spectators: " a { href: "/room.php/{code}/watch", "watch this game" } }
+            ul {
+                cx.props.players.iter().map(|player| rsx! { li { "{player}" } })
+            }
+            p { id: "watchers", "Watching: {cx.props.watchers}" }
+            p { "{cx.props.status}" }
+            if !cx.props.message.is_empty() {
+                rsx! { p { "{cx.props.message}" } }
+            }
+            if cx.props.can_join {
+                rsx! {
+                    form {
+                        action: "/room.php/{code}/join",
+                        method: "POST",
+                        label { r#for: "name", "Name: " }
+                        input { id: "name", name: "name", r#type: "text", required: true }
+                        input { r#type: "submit", value: "Join" }
+                    }
+                }
+            }
+            cx.props.grids.iter().map(|grid| {
+                rsx! {
+                    h4 { "{grid.title}" }
+                    table {
+                        tbody {
+                            grid.cells.iter().map(|row| {
+                                rsx! {
+                                    tr {
+                                        row.iter().map(|(symbol, mv)| {
+                                            rsx! {
+                                                td {
+                                                    width: "50px",
+                                                    height: "50px",
+                                                    border: "1px solid black",
+                                                    text_align: "center",
+                                                    if let Some(mv) = mv {
+                                                        rsx! {
+                                                            form {
+                                                                action: "/room.php/{code}/move",
+                                                                method: "POST",
+                                                                button { r#type: "submit", name: "move", value: "{mv}", "?" }
+                                                            }
+                                                        }
+                                                    } else {
+                                                        rsx! { "{symbol}" }
+                                                    }
+                                                }
+                                            }
+                                        })
+                                    }
+                                }
+                            })
+                        }
+                    }
+                }
+            })
+            a { href: "/room.php", "Back to the lobby" }
+        }
+    })
+}
+
+// reloads the page whenever the game changes, see `/ws/{code}`. when the
+// socket cannot connect or drops, a page that is still waiting polls instead
+fn live_updates(code: &str, poll: bool) -> String {
+    format!(
+        r#"
+const scheme = location.protocol === "https:" ? "wss://" : "ws://";
+const socket = new WebSocket(scheme + location.host + "/ws/{code}");
+socket.onmessage = (message) => {{
+    const update = JSON.parse(message.data);
+    if (update.event === "watchers") {{
+        document.getElementById("watchers").textContent = "Watching: " + update.watchers;
+    }} else if (update.event !== "state") {{
+        location.reload();
+    }}
+}};
+const fallback = () => {{
+    if ({poll}) {{
+        setTimeout(() => location.reload(), 2000);
+    }}
+    socket.onerror = socket.onclose = null;
+}};
+socket.onerror = fallback;
+socket.onclose = fallback;
+"#
+    )
+}
+
+pub fn lobby_html() -> String {
+    let mut app = VirtualDom::new(Lobby);
+    let _ = app.rebuild();
+    format!(
+        "<!DOCTYPE html><html lang='en'>{}</html",
+        dioxus_ssr::render(&app)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn full_room(game: &str) -> Room {
+        let mut room = Room::new(game).unwrap();
+        assert_eq!(room.join("alice-token", "alice"), Ok(0));
+        assert_eq!(room.join("bob-token", "bob"), Ok(1));
+        room
+    }
+
+    #[test]
+    fn nobody_moves_before_the_second_player_joins() {
+        let mut room = Room::new("ttt:3x3").unwrap();
+        room.join("alice-token", "alice").unwrap();
+        assert_eq!(room.turn(), None);
+        assert_eq!(
+            room.play("alice-token", "1,1"),
+            Err("Waiting for an opponent".to_owned())
+        );
+    }
+
+    #[test]
+    fn players_move_in_turn() {
+        let mut room = full_room("connect:5x7");
+        assert_eq!(room.turn(), Some(0));
+        assert_eq!(
+            room.play("bob-token", "3"),
+            Err("It is not your turn".to_owned())
+        );
+        room.play("alice-token", "3").unwrap();
+        assert_eq!(room.turn(), Some(1));
+        assert_eq!(
+            room.play("alice-token", "3"),
+            Err("It is not your turn".to_owned())
+        );
+        // a rejected move keeps the turn
+        assert!(room.play("bob-token", "9").is_err());
+        assert_eq!(room.turn(), Some(1));
+        room.play("bob-token", "3").unwrap();
+        assert_eq!(room.turn(), Some(0));
+    }
+
+    #[test]
+    fn only_seated_players_move() {
+        let mut room = full_room("ttt:3x3");
+        assert_eq!(
+            room.play("carol-token", "1,1"),
+            Err("You are not playing in this room".to_owned())
+        );
+        assert_eq!(
+            room.join("carol-token", "carol"),
+            Err("The room is full".to_owned())
+        );
+        // joining again keeps the seat
+        assert_eq!(room.join("bob-token", "bob"), Ok(1));
+    }
+
+    #[test]
+    fn nobody_moves_after_the_game() {
+        let mut room = full_room("ttt:3x3");
+        for (token, mv) in [
+            ("alice-token", "0,0"),
+            ("bob-token", "1,0"),
+            ("alice-token", "0,1"),
+            ("bob-token", "1,1"),
+            ("alice-token", "0,2"),
+        ] {
+            room.play(token, mv).unwrap();
+        }
+        assert_eq!(room.outcome(), Outcome::Win(Player::X));
+        assert_eq!(room.turn(), None);
+        for token in ["alice-token", "bob-token"] {
+            assert_eq!(room.play(token, "2,2"), Err("Game is over".to_owned()));
+        }
+    }
+
+    #[test]
+    fn battleship_seats_take_turns() {
+        let mut room = full_room("battleship");
+        for shot in 0..4 {
+            let cell = format!("{},{}", shot / battleship::COLS, shot % battleship::COLS);
+            assert_eq!(room.turn(), Some(0));
+            assert_eq!(
+                room.play("bob-token", &cell),
+                Err("It is not your turn".to_owned())
+            );
+            room.play("alice-token", &cell).unwrap();
+            assert_eq!(room.turn(), Some(1));
+            assert_eq!(
+                room.play("alice-token", &cell),
+                Err("It is not your turn".to_owned())
+            );
+            room.play("bob-token", &cell).unwrap();
+        }
+        let RoomGame::Battleship(boards) = &room.game else {
+            unreachable!()
+        };
+        assert_eq!(boards[0].shots.len(), 4);
+        assert_eq!(boards[1].shots.len(), 4);
+    }
+}
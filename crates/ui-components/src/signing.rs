@@ -0,0 +1,115 @@
+// HMAC signatures for board states handed to the client
+//
+// every board link or button carries a `sig` parameter next to the plain
+// `board`, so a board that was edited by hand no longer verifies
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::fmt;
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct Signer {
+    key: Vec<u8>,
+}
+
+impl Signer {
+    pub fn new(secret: &str) -> Self {
+        Signer {
+            key: secret.as_bytes().to_vec(),
+        }
+    }
+
+    fn mac(&self, game: &str, board: &str) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("HMAC accepts any key length");
+        mac.update(game.as_bytes());
+        mac.update(b"\n");
+        mac.update(board.as_bytes());
+        mac
+    }
+
+    // hex signature of a board for one game, e.g. "ttt:3x3"
+    pub fn sign(&self, game: &str, board: &str) -> String {
+        self.mac(game, board)
+            .finalize()
+            .into_bytes()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect()
+    }
+
+    pub fn verify(&self, game: &str, board: &str, signature: &str) -> bool {
+        let Some(bytes) = decode_hex(signature) else {
+            return false;
+        };
+        self.mac(game, board).verify_slice(&bytes).is_ok()
+    }
+}
+
+// never print the key
+impl fmt::Debug for Signer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Signer").finish_non_exhaustive()
+    }
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOARD: &str = "X O  X O ";
+
+    #[test]
+    fn signed_boards_verify() {
+        let signer = Signer::new("secret");
+        let sig = signer.sign("ttt:3x3", BOARD);
+        assert_eq!(sig.len(), 64);
+        assert!(signer.verify("ttt:3x3", BOARD, &sig));
+        assert!(signer.verify("ttt:3x3", BOARD, &sig.to_uppercase()));
+        assert!(!Signer::new("other").verify("ttt:3x3", BOARD, &sig));
+    }
+
+    #[test]
+    fn changed_boards_fail() {
+        let signer = Signer::new("secret");
+        let sig = signer.sign("ttt:3x3", BOARD);
+        assert!(!signer.verify("ttt:3x3", "X O  X OX", &sig));
+        assert!(!signer.verify("ttt:3x3", "", &sig));
+    }
+
+    #[test]
+    fn other_games_fail() {
+        let signer = Signer::new("secret");
+        let sig = signer.sign("ttt:3x3", BOARD);
+        assert!(!signer.verify("ttt:4x4", BOARD, &sig));
+        assert!(!signer.verify("connect:5x7", BOARD, &sig));
+    }
+
+    #[test]
+    fn malformed_signatures_fail() {
+        let signer = Signer::new("secret");
+        let sig = signer.sign("ttt:3x3", BOARD);
+        for bad in [
+            "",
+            &sig[1..],
+            &sig[..62],
+            &format!("{}zz", &sig[..62]),
+            &format!("{sig}00"),
+            "not hex",
+        ] {
+            assert!(!signer.verify("ttt:3x3", BOARD, bad), "{bad:?}");
+        }
+        // a multibyte character cannot split a hex pair
+        assert!(!signer.verify("ttt:3x3", BOARD, &format!("0é{}", &sig[3..])));
+    }
+}
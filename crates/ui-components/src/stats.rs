@@ -0,0 +1,459 @@
+// results of finished games and the leaderboard and player pages built from
+// them
+//
+// every result is told from the side of the player it belongs to, so a room
+// game gives one result to each seat
+use std::collections::HashMap;
+
+use dioxus::prelude::*;
+
+use crate::game::{Outcome, Player};
+use crate::record::{percent_encode, Record};
+
+// every game with a column on the leaderboard, with its title
+pub const GAMES: [(&str, &str); 3] = [
+    ("ttt", "Tic-tac-toe"),
+    ("connect", "Connect-4"),
+    ("battleship", "Battleship"),
+];
+
+// how many players each leaderboard table shows
+pub const LEADERS: usize = 10;
+// how many games the player page lists
+const RECENT: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlayerResult {
+    Win,
+    Loss,
+    Draw,
+}
+
+impl PlayerResult {
+    // the outcome as seen by `player`
+    pub fn for_player(outcome: Outcome, player: Player) -> Option<PlayerResult> {
+        match outcome {
+            Outcome::Win(winner) if winner == player => Some(PlayerResult::Win),
+            Outcome::Win(_) => Some(PlayerResult::Loss),
+            Outcome::Draw => Some(PlayerResult::Draw),
+            Outcome::InProgress => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PlayerResult::Win => "win",
+            PlayerResult::Loss => "loss",
+            PlayerResult::Draw => "draw",
+        }
+    }
+
+    // the result of the other side of the same game
+    pub fn opposite(self) -> PlayerResult {
+        match self {
+            PlayerResult::Win => PlayerResult::Loss,
+            PlayerResult::Loss => PlayerResult::Win,
+            PlayerResult::Draw => PlayerResult::Draw,
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<PlayerResult> {
+        match name {
+            "win" => Some(PlayerResult::Win),
+            "loss" => Some(PlayerResult::Loss),
+            "draw" => Some(PlayerResult::Draw),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameResult {
+    pub player: String,
+    pub game: String,
+    pub variant: String,
+    pub result: PlayerResult,
+    pub moves: usize,
+    // None when the start of the game is not known
+    pub seconds: Option<i64>,
+    // when the game ended, utc
+    pub finished: String,
+}
+
+impl GameResult {
+    // the result of the player of a finished record, who played X
+    pub fn from_record(record: &Record, seconds: Option<i64>) -> Option<GameResult> {
+        Some(GameResult {
+            player: record.player.clone(),
+            game: record.game.clone(),
+            variant: record.variant.clone(),
+            result: PlayerResult::for_player(record.result, Player::X)?,
+            moves: record.moves.len(),
+            seconds,
+            finished: chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        })
+    }
+}
+
+// one player's results in one game
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Summary {
+    pub player: String,
+    pub game: String,
+    pub played: usize,
+    pub wins: usize,
+    pub losses: usize,
+    pub draws: usize,
+    // wins in a row up to the latest game
+    pub streak: usize,
+    pub best_streak: usize,
+    pub total_moves: usize,
+    pub total_seconds: i64,
+    // games whose duration is known
+    pub timed: usize,
+}
+
+impl Summary {
+    pub fn win_rate(&self) -> f64 {
+        if self.played == 0 {
+            0.0
+        } else {
+            self.wins as f64 * 100.0 / self.played as f64
+        }
+    }
+
+    fn add(&mut self, result: &GameResult) {
+        self.played += 1;
+        match result.result {
+            PlayerResult::Win => self.wins += 1,
+            PlayerResult::Loss => self.losses += 1,
+            PlayerResult::Draw => self.draws += 1,
+        }
+        (self.streak, self.best_streak) =
+            next_streak((self.streak, self.best_streak), result.result);
+        self.total_moves += result.moves;
+        if let Some(seconds) = result.seconds {
+            self.total_seconds += seconds;
+            self.timed += 1;
+        }
+    }
+
+    fn average_moves(&self) -> String {
+        match self.played {
+            0 => "-".to_owned(),
+            played => format!("{:.1}", self.total_moves as f64 / played as f64),
+        }
+    }
+
+    fn average_time(&self) -> String {
+        match self.timed {
+            0 => "-".to_owned(),
+            timed => duration(self.total_seconds / timed as i64),
+        }
+    }
+}
+
+// the current and best streaks once `result` is added to them
+fn next_streak((streak, best): (usize, usize), result: PlayerResult) -> (usize, usize) {
+    match result {
+        PlayerResult::Win => (streak + 1, best.max(streak + 1)),
+        PlayerResult::Loss | PlayerResult::Draw => (0, best),
+    }
+}
+
+// the wins in a row up to the latest result and the most wins in a row,
+// `results` must be oldest first
+pub fn streaks(results: impl IntoIterator<Item = PlayerResult>) -> (usize, usize) {
+    results.into_iter().fold((0, 0), next_streak)
+}
+
+fn duration(seconds: i64) -> String {
+    if seconds < 60 {
+        format!("{seconds}s")
+    } else {
+        format!("{}m {:02}s", seconds / 60, seconds % 60)
+    }
+}
+
+// one summary per player and game in the order they first played, `results`
+// must be oldest first
+pub fn summarize(results: &[GameResult]) -> Vec<Summary> {
+    let mut summaries: Vec<Summary> = vec![];
+    let mut index: HashMap<(&str, &str), usize> = HashMap::new();
+    for result in results {
+        let at = *index
+            .entry((&result.player, &result.game))
+            .or_insert_with(|| {
+                summaries.push(Summary {
+                    player: result.player.clone(),
+                    game: result.game.clone(),
+                    ..Summary::default()
+                });
+                summaries.len() - 1
+            });
+        summaries[at].add(result);
+    }
+    summaries
+}
+
+fn game_title(game: &str) -> &str {
+    GAMES
+        .iter()
+        .find(|(name, _)| *name == game)
+        .map_or(game, |(_, title)| title)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Table {
+    title: String,
+    rows: Vec<Summary>,
+}
+
+#[derive(Debug, Clone, PartialEq, Props)]
+struct LeaderboardProps {
+    tables: Vec<Table>,
+}
+
+#[component]
+fn Leaderboard(cx: Scope<LeaderboardProps>) -> Element {
+    cx.render(rsx! {
+        body {
+            h3 { "Leaderboard" }
+            cx.props.tables.iter().map(|table| rsx! {
+                h4 { "{table.title}" }
+                if table.rows.is_empty() {
+                    rsx! { p { "No games yet" } }
+                } else {
+                    rsx! {
+                        table {
+                            thead {
+                                tr {
+                                    th { "#" }
+                                    th { "Player" }
+                                    th { "Played" }
+                                    th { "Won" }
+                                    th { "Win rate" }
+                                    th { "Best streak" }
+                                }
+                            }
+                            tbody {
+                                table.rows.iter().enumerate().map(|(rank, summary)| rsx! {
+                                    tr {
+                                        td { "{rank + 1}" }
+                                        td { a { href: "/leaderboard/{percent_encode(&summary.player)}", "{summary.player}" } }
+                                        td { "{summary.played}" }
+                                        td { "{summary.wins}" }
+                                        td { "{summary.win_rate():.0}%" }
+                                        td { "{summary.best_streak}" }
+                                    }
+                                })
+                            }
+                        }
+                    }
+                }
+            })
+        }
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Props)]
+struct PlayerPageProps {
+    player: String,
+    summaries: Vec<Summary>,
+    recent: Vec<GameResult>,
+}
+
+#[component]
+fn PlayerPage(cx: Scope<PlayerPageProps>) -> Element {
+    cx.render(rsx! {
+        body {
+            h3 { "{cx.props.player}" }
+            if cx.props.summaries.is_empty() {
+                rsx! { p { "No finished games yet" } }
+            } else {
+                rsx! {
+                    table {
+                        thead {
+                            tr {
+                                th { "Game" }
+                                th { "Played" }
+                                th { "Won" }
+                                th { "Lost" }
+                                th { "Drawn" }
+                                th { "Win rate" }
+                                th { "Streak" }
+                                th { "Best streak" }
+                                th { "Average moves" }
+                                th { "Average time" }
+                            }
+                        }
+                        tbody {
+                            cx.props.summaries.iter().map(|summary| rsx! {
+                                tr {
+                                    td { "{game_title(&summary.game)}" }
+                                    td { "{summary.played}" }
+                                    td { "{summary.wins}" }
+                                    td { "{summary.losses}" }
+                                    td { "{summary.draws}" }
+                                    td { "{summary.win_rate():.0}%" }
+                                    td { "{summary.streak}" }
+                                    td { "{summary.best_streak}" }
+                                    td { "{summary.average_moves()}" }
+                                    td { "{summary.average_time()}" }
+                                }
+                            })
+                        }
+                    }
+                    h4 { "Recent games" }
+                    ol {
+                        cx.props.recent.iter().map(|result| {
+                            let time = result.seconds.map(duration).unwrap_or_default();
+                            rsx! {
+                                li { "{result.finished}: {game_title(&result.game)} {result.variant}, {result.result.name()} in {result.moves} moves {time}" }
+                            }
+                        })
+                    }
+                }
+            }
+            a { href: "/leaderboard", "Back to the leaderboard" }
+        }
+    })
+}
+
+// the best players of each game by wins, then win rate, out of `summaries`
+pub fn leaderboard_html(summaries: &[Summary]) -> String {
+    let tables = GAMES
+        .iter()
+        .map(|(game, title)| {
+            let mut rows: Vec<Summary> = summaries
+                .iter()
+                .filter(|summary| summary.game == *game)
+                .cloned()
+                .collect();
+            rows.sort_by(|a, b| {
+                b.wins
+                    .cmp(&a.wins)
+                    .then(b.win_rate().total_cmp(&a.win_rate()))
+                    .then(a.player.cmp(&b.player))
+            });
+            rows.truncate(LEADERS);
+            Table {
+                title: title.to_string(),
+                rows,
+            }
+        })
+        .collect();
+    let mut app = VirtualDom::new_with_props(Leaderboard, LeaderboardProps { tables });
+    let _ = app.rebuild();
+    format!(
+        "<!DOCTYPE html><html lang='en'>{}</html",
+        dioxus_ssr::render(&app)
+    )
+}
+
+// `results` are the player's own, oldest first
+pub fn player_html(player: &str, results: &[GameResult]) -> String {
+    let mut app = VirtualDom::new_with_props(
+        PlayerPage,
+        PlayerPageProps {
+            player: player.to_owned(),
+            summaries: summarize(results),
+            recent: results.iter().rev().take(RECENT).cloned().collect(),
+        },
+    );
+    let _ = app.rebuild();
+    format!(
+        "<!DOCTYPE html><html lang='en'>{}</html",
+        dioxus_ssr::render(&app)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use PlayerResult::*;
+
+    fn result(player: &str, game: &str, result: PlayerResult, seconds: Option<i64>) -> GameResult {
+        GameResult {
+            player: player.to_owned(),
+            game: game.to_owned(),
+            variant: String::new(),
+            result,
+            moves: 4,
+            seconds,
+            finished: String::new(),
+        }
+    }
+
+    #[test]
+    fn streaks_count_wins_in_a_row() {
+        assert_eq!(streaks([]), (0, 0));
+        assert_eq!(streaks([Win, Win, Loss, Win]), (1, 2));
+        assert_eq!(streaks([Win, Draw, Win, Win, Win]), (3, 3));
+        assert_eq!(streaks([Win, Win, Win, Draw]), (0, 3));
+    }
+
+    #[test]
+    fn win_rate_is_a_percentage() {
+        assert_eq!(Summary::default().win_rate(), 0.0);
+        let summary = Summary {
+            played: 4,
+            wins: 3,
+            ..Summary::default()
+        };
+        assert_eq!(summary.win_rate(), 75.0);
+    }
+
+    #[test]
+    fn summarize_groups_by_player_and_game() {
+        let results = [
+            result("bob", "ttt", Win, Some(10)),
+            result("alice", "ttt", Loss, None),
+            result("bob", "connect", Draw, Some(30)),
+            result("bob", "ttt", Win, Some(20)),
+            result("bob", "ttt", Loss, None),
+            result("bob", "ttt", Win, None),
+        ];
+        let summaries = summarize(&results);
+        let keys: Vec<(&str, &str)> = summaries
+            .iter()
+            .map(|summary| (summary.player.as_str(), summary.game.as_str()))
+            .collect();
+        assert_eq!(keys, [("bob", "ttt"), ("alice", "ttt"), ("bob", "connect")]);
+        assert_eq!(
+            summaries[0],
+            Summary {
+                player: "bob".to_owned(),
+                game: "ttt".to_owned(),
+                played: 4,
+                wins: 3,
+                losses: 1,
+                draws: 0,
+                streak: 1,
+                best_streak: 2,
+                total_moves: 16,
+                total_seconds: 30,
+                timed: 2,
+            }
+        );
+        assert_eq!(summaries[0].average_time(), "15s");
+        assert_eq!(summaries[1].average_time(), "-");
+        assert_eq!((summaries[2].draws, summaries[2].played), (1, 1));
+    }
+
+    #[test]
+    fn leaderboard_ranks_by_wins_then_win_rate() {
+        let results = [
+            result("carol", "ttt", Win, None),
+            result("alice", "ttt", Win, None),
+            result("alice", "ttt", Loss, None),
+            result("bob", "ttt", Win, None),
+            result("bob", "ttt", Win, None),
+        ];
+        let page = leaderboard_html(&summarize(&results));
+        let bob = page.find(">bob<").unwrap();
+        let carol = page.find(">carol<").unwrap();
+        let alice = page.find(">alice<").unwrap();
+        assert!(bob < carol && carol < alice, "{page}");
+    }
+}
@@ -2,111 +2,225 @@
 #![allow(clippy::needless_range_loop)]
 use dioxus::prelude::*;
 use rand::Rng;
+use serde::{Deserialize, Serialize};
 use std::fmt;
+use std::str::FromStr;
+use utoipa::ToSchema;
+
+use crate::game::{self, Game, Outcome, Player};
+use crate::mnk::Grid;
+pub use crate::mnk::ParseError;
+pub use crate::mnk::Variant;
+use crate::record::{self, Diagram, Record, Replay};
+use crate::signing::Signer;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Difficulty {
+    // random empty cell
+    Easy,
+    // the best move half of the time, random otherwise
+    Medium,
+    // the best move the server knows: a full minimax search on 3x3, which
+    // never loses, and a one-move lookahead on 4x4 and gomoku, which can
+    // be beaten
+    #[default]
+    Perfect,
+}
+
+impl fmt::Display for Difficulty {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Difficulty::Easy => write!(f, "easy"),
+            Difficulty::Medium => write!(f, "medium"),
+            Difficulty::Perfect => write!(f, "perfect"),
+        }
+    }
+}
 
 #[derive(Debug, Clone, PartialEq)]
-struct Board {
-    chips: [[String; 3]; 3],
+pub struct Board {
+    grid: Grid,
+    // moves that led to this board, oldest first, empty if unknown
+    history: Vec<(usize, usize)>,
 }
 
 impl Board {
     const DRAW_MESG: &'static str =
         "WINNER: NONE. A STRANGE GAME. THE ONLY WINNING MOVE IS NOT TO PLAY.";
 
-    fn new() -> Self {
+    pub fn with_variant(variant: Variant) -> Self {
         Board {
-            chips: Default::default(),
+            grid: Grid::new(variant),
+            history: vec![],
         }
     }
 
-    fn from(encoding: &str) -> Self {
-        let mut chips: [[String; 3]; 3] = Default::default();
-        let chars = encoding.chars().collect::<Vec<char>>();
-        let mut idx = 0;
-        for &value in chars.iter() {
-            if value == ' ' {
-                idx += 1;
-            } else {
-                chips[idx / 3][idx % 3] = value.to_string();
-            }
-            if idx > 8 {
-                break;
-            }
+    // cells are separated by single spaces and hold X, O or nothing
+    pub fn parse(variant: Variant, encoding: &str) -> Result<Self, ParseError> {
+        let symbols: Vec<&str> = encoding.split(' ').collect();
+        if symbols.len() != variant.cells() {
+            return Err(ParseError::WrongLength {
+                expected: variant.cells(),
+                found: symbols.len(),
+            });
         }
-        Board { chips }
+        Ok(Board {
+            grid: Grid::from_symbols(variant, symbols)?,
+            history: vec![],
+        })
     }
 
-    fn is_full(&self) -> bool {
-        for i in 0..3 {
-            for j in 0..3 {
-                if self.chips[i][j].is_empty() {
-                    return false;
-                }
-            }
+    pub(crate) fn grid(&self) -> &Grid {
+        &self.grid
+    }
+
+    // moves are "row,col" separated by dots, e.g. "1,1.0,2"
+    pub fn history(&self) -> String {
+        self.history
+            .iter()
+            .map(|(row, col)| format!("{row},{col}"))
+            .collect::<Vec<String>>()
+            .join(".")
+    }
+
+    fn replay(variant: Variant, moves: &[(usize, usize)]) -> Option<Board> {
+        let mut board = Board::with_variant(variant);
+        for &mv in moves {
+            board.apply_move(mv).ok()?;
         }
-        true
+        Some(board)
     }
 
-    fn make_move(&mut self, row: usize, col: usize, player: &str) -> Result<(), String> {
-        if self.chips[row][col].is_empty() {
-            self.chips[row][col] = player.to_string();
-            return Ok(());
+    // attaches a move list to a parsed board, it is dropped unless replaying
+    // it from an empty board gives exactly this board
+    pub fn with_history(self, encoding: &str) -> Board {
+        let moves: Option<Vec<(usize, usize)>> = encoding
+            .split('.')
+            .filter(|mv| !mv.is_empty())
+            .map(|mv| {
+                let (row, col) = mv.split_once(',')?;
+                Some((row.parse().ok()?, col.parse().ok()?))
+            })
+            .collect();
+        match moves.and_then(|moves| Board::replay(self.grid.variant(), &moves)) {
+            Some(board) if board.grid == self.grid => board,
+            _ => self,
         }
-        Err("Cannot place player at location".to_string())
     }
 
-    fn has_win(&self) -> Option<&str> {
-        // across, down, diagonal
-        const DX: [i32; 4] = [0, -1, -1, -1];
-        const DY: [i32; 4] = [-1, 0, 1, -1];
-        for i in 0..3 {
-            for j in 0..3 {
-                if self.chips[i][j].is_empty() {
-                    continue;
-                }
-                for k in 0..DX.len() {
-                    let mut count = 0;
-                    for l in 0..3 {
-                        let (nx, ny) = ((i as i32) + DX[k] * l, (j as i32) + DY[k] * l);
-                        let in_bounds = (0..3).contains(&nx) && (0..3).contains(&ny);
-                        if !in_bounds {
-                            break;
-                        }
-                        if self.chips[nx as usize][ny as usize] == self.chips[i][j] {
-                            count += 1;
-                        }
-                    }
-                    if count == 3 {
-                        return Some(&self.chips[i][j]);
-                    }
-                }
-            }
+    // the board before the last X/O pair, or before X's last move if the
+    // server never got to reply, so it is X's turn again
+    pub fn undo(&self) -> Option<Board> {
+        if self.history.is_empty() {
+            return None;
         }
-        None
+        let keep = (self.history.len() - 1) / 2 * 2;
+        Board::replay(self.grid.variant(), &self.history[..keep])
     }
 
-    fn get_state(&self) -> &'static str {
-        match self.has_win() {
-            Some("X") => "You won!",
-            Some("O") => "I won!",
-            Some(_) => panic!("Invalid state"),
-            None => {
-                if self.is_full() {
-                    Board::DRAW_MESG
+    // moves played so far, 0 if the history is unknown
+    pub fn move_count(&self) -> usize {
+        self.history.len()
+    }
+
+    pub fn record(&self, name: &str) -> Record {
+        let mut record = Record::new("ttt", name, self.grid.variant().name(), self.outcome());
+        record.moves = self
+            .history
+            .iter()
+            .map(|&(row, col)| record::square(row, col))
+            .collect();
+        record
+    }
+
+    // plays the server's answer to X's move, returns it unless the game is
+    // over or it is not the server's turn
+    pub fn reply(&mut self, difficulty: Difficulty) -> Option<(usize, usize)> {
+        if self.outcome().is_over() || self.current_player() != Player::O {
+            return None;
+        }
+        let mv = self.choose_move(difficulty)?;
+        self.apply_move(mv).ok()?;
+        Some(mv)
+    }
+
+    fn choose_move(&self, difficulty: Difficulty) -> Option<(usize, usize)> {
+        let best_move = || {
+            // only the classic board is small enough to search exhaustively
+            if self.grid.variant() == Variant::TIC_TAC_TOE {
+                game::best_move(self)
+            } else {
+                self.grid.heuristic_move(self.current_player())
+            }
+        };
+        match difficulty {
+            Difficulty::Easy => game::random_move(self),
+            Difficulty::Medium => {
+                if rand::thread_rng().gen_bool(0.5) {
+                    best_move()
                 } else {
-                    ""
+                    game::random_move(self)
                 }
             }
+            Difficulty::Perfect => best_move(),
+        }
+    }
+
+    fn get_state(&self) -> &'static str {
+        match self.outcome() {
+            Outcome::Win(Player::X) => "You won!",
+            Outcome::Win(Player::O) => "I won!",
+            Outcome::Draw => Board::DRAW_MESG,
+            Outcome::InProgress => "",
+        }
+    }
+}
+
+impl Game for Board {
+    type Move = (usize, usize);
+
+    fn new() -> Self {
+        Board::with_variant(Variant::TIC_TAC_TOE)
+    }
+
+    fn current_player(&self) -> Player {
+        self.grid.current_player()
+    }
+
+    fn legal_moves(&self) -> Vec<Self::Move> {
+        if self.outcome().is_over() {
+            return vec![];
         }
+        self.grid.open_cells()
+    }
+
+    fn apply_move(&mut self, (row, col): Self::Move) -> Result<(), String> {
+        let player = self.current_player();
+        self.grid.place(row, col, player)?;
+        self.history.push((row, col));
+        Ok(())
+    }
+
+    fn outcome(&self) -> Outcome {
+        self.grid.outcome()
+    }
+
+    fn encode(&self) -> String {
+        self.to_string()
+    }
+
+    fn decode(encoding: &str) -> Result<Self, String> {
+        encoding.parse().map_err(|err: ParseError| err.to_string())
     }
 }
 
 impl fmt::Display for Board {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let mut encoded: String = String::new();
-        for i in 0..3 {
-            for j in 0..3 {
-                encoded.push_str(&self.chips[i][j]);
+        for row in self.grid.rows() {
+            for cell in row {
+                encoded.push_str(cell.map_or("", Player::symbol));
                 encoded.push(' ');
             }
         }
@@ -115,16 +229,51 @@ impl fmt::Display for Board {
     }
 }
 
+// the variant is inferred from the number of cells
+impl FromStr for Board {
+    type Err = ParseError;
+
+    fn from_str(encoding: &str) -> Result<Self, Self::Err> {
+        let cells = encoding.split(' ').count();
+        let variant = Variant::all(false)
+            .find(|variant| variant.cells() == cells)
+            .ok_or(ParseError::WrongLength {
+                expected: Variant::TIC_TAC_TOE.cells(),
+                found: cells,
+            })?;
+        Board::parse(variant, encoding)
+    }
+}
+
+// tic-tac-toe variants are the ones without gravity
+pub fn variant_from_name(name: &str) -> Option<Variant> {
+    Variant::from_name(name).filter(|variant| !variant.gravity)
+}
+
 #[derive(Debug, Clone, PartialEq, Props)]
 struct GameProps {
     name: String,
     board: Board,
+    difficulty: Difficulty,
+    #[props(!optional)]
+    signer: Option<Signer>,
 }
 
 #[derive(Debug, Clone, PartialEq, Props)]
 struct PlayProps {
     name: String,
-    encoding: String,
+    board: Board,
+    difficulty: Difficulty,
+    #[props(!optional)]
+    signer: Option<Signer>,
+}
+
+#[derive(Debug, Clone, PartialEq, Props)]
+struct BadBoardProps {
+    name: String,
+    difficulty: Difficulty,
+    variant: Variant,
+    error: ParseError,
 }
 
 #[derive(Debug, Clone, PartialEq, Props)]
@@ -145,6 +294,22 @@ fn Home(cx: Scope) -> Element {
                 method: "GET",
                 label { r#for: "name", "Name: "}
                 input { id: "name", name: "name", r#type: "text", required: true }
+                label { r#for: "difficulty", "Difficulty: "}
+                select {
+                    id: "difficulty",
+                    name: "difficulty",
+                    option { value: "easy", "Easy" }
+                    option { value: "medium", "Medium" }
+                    option { value: "perfect", selected: true, "Perfect" }
+                }
+                label { r#for: "variant", "Board: "}
+                select {
+                    id: "variant",
+                    name: "variant",
+                    option { value: "3x3", selected: true, "3x3" }
+                    option { value: "4x4", "4x4" }
+                    option { value: "gomoku", "Gomoku (15x15)" }
+                }
                 input { r#type: "submit", value: "Submit" }
             }
         }
@@ -153,46 +318,57 @@ fn Home(cx: Scope) -> Element {
 
 #[component]
 fn Game(cx: Scope<GameProps>) -> Element {
-    let mut states: [[String; 3]; 3] = Default::default();
-    for i in 0..3 {
-        for j in 0..3 {
-            let mut board = cx.props.board.clone();
-            match board.make_move(i, j, "X") {
-                Ok(()) => states[i][j] = board.to_string(),
-                Err(_) => continue,
-            }
+    let board = &cx.props.board;
+    let variant = board.grid.variant();
+    let mut states = vec![vec![(String::new(), String::new()); variant.cols]; variant.rows];
+    for (i, j) in board.grid.open_cells() {
+        let mut next = board.clone();
+        if next.apply_move((i, j)).is_ok() {
+            states[i][j] = (next.to_string(), board_params(&next, &cx.props.signer));
         }
     }
-    let is_end = cx.props.board.has_win().is_some() || cx.props.board.is_full();
+    let is_end = board.outcome().is_over();
+    let undo = board.undo().map(|previous| {
+        (
+            previous.to_string(),
+            board_params(&previous, &cx.props.signer),
+        )
+    });
+    // dioxus-ssr leaves attribute values as they are
+    let name = &record::percent_encode(&cx.props.name);
     cx.render(rsx! {
-        table {
-            tbody {
-                for (i, row) in states.iter().enumerate() {
-                    rsx! {
-                        tr {
-                            for (j, state) in row.iter().enumerate() {
-                                rsx! {
-                                    if cx.props.board.chips[i][j].is_empty() && !is_end{
-                                        rsx! {
-                                            td {
-                                                width: "50px",
-                                                height: "50px",
-                                                border: "1px solid black",
-                                                a {
-                                                    href: "/ttt.php?name={cx.props.name}&board={state}",
-                                                    style: "width: 100%; height: 100%; display: flex; justify-content: center; align-items: center;",
-                                                    " "
+        div {
+            display: "flex",
+            gap: "20px",
+            table {
+                tbody {
+                    for (i, row) in states.iter().enumerate() {
+                        rsx! {
+                            tr {
+                                for (j, (state, params)) in row.iter().enumerate() {
+                                    rsx! {
+                                        if board.grid.get(i, j).is_none() && !is_end {
+                                            rsx! {
+                                                td {
+                                                    width: "50px",
+                                                    height: "50px",
+                                                    border: "1px solid black",
+                                                    a {
+                                                        href: "/ttt.php?name={name}&board={state}&difficulty={cx.props.difficulty}&variant={variant}{params}",
+                                                        style: "width: 100%; height: 100%; display: flex; justify-content: center; align-items: center;",
+                                                        " "
+                                                    }
                                                 }
                                             }
-                                        }
-                                    } else {
-                                        rsx! {
-                                            td {
-                                                width: "50px",
-                                                height: "50px",
-                                                border: "1px solid black",
-                                                text_align: "center",
-                                                cx.props.board.chips[i][j].as_str()
+                                        } else {
+                                            rsx! {
+                                                td {
+                                                    width: "50px",
+                                                    height: "50px",
+                                                    border: "1px solid black",
+                                                    text_align: "center",
+                                                    board.grid.get(i, j).map_or("", Player::symbol)
+                                                }
                                             }
                                         }
                                     }
@@ -202,6 +378,17 @@ fn Game(cx: Scope<GameProps>) -> Element {
                     }
                 }
             }
+            ol {
+                board.history.iter().enumerate().map(|(n, (row, col))| {
+                    let player = if n % 2 == 0 { Player::X } else { Player::O };
+                    rsx! { li { "{player}: row {row + 1}, column {col + 1}" } }
+                })
+            }
+        }
+        if let Some((state, params)) = undo {
+            rsx! {
+                a { href: "/ttt.php?name={name}&board={state}&difficulty={cx.props.difficulty}&variant={variant}{params}", "Undo" }
+            }
         }
     })
 }
@@ -210,46 +397,46 @@ fn Game(cx: Scope<GameProps>) -> Element {
 fn Play(cx: Scope<PlayProps>) -> Element {
     let name = cx.props.name.to_string();
     let date = chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
-    let board = match cx.props.encoding.as_str() {
-        "" => Board::new(),
-        "        " => Board::new(),
-        encoding => {
-            let mut board = Board::from(encoding);
-            if board.get_state().is_empty() {
-                let mut rng = rand::thread_rng();
-                let mut positions = Vec::new();
-                for i in 0..3 {
-                    for j in 0..3 {
-                        if board.chips[i][j].is_empty() {
-                            positions.push((i, j));
-                        }
-                    }
-                }
-                // make a random move
-                if !positions.is_empty() {
-                    let (i, j) = positions[rng.gen_range(0..positions.len())];
-                    let _ = board.make_move(i, j, "O");
-                }
-            }
-            board
-        }
-    };
+    let board = cx.props.board.clone();
+    let variant = board.grid.variant();
     let state = board.get_state();
+    let record = board.record(&name);
+    let url_name = record::percent_encode(&name);
 
     cx.render(rsx! {
         if !state.is_empty() {
             rsx! { p { "{state}" } }
         }
         p { "Hello {name}, {date}" }
-        Game { name: name, board: board }
+        Game {
+            name: name,
+            board: board,
+            difficulty: cx.props.difficulty,
+            signer: cx.props.signer.clone()
+        }
+        if !state.is_empty() && !record.moves.is_empty() {
+            rsx! {
+                a { download: "{record.file_name()}", href: "{record.data_url()}", "Download game" }
+            }
+        }
         if !state.is_empty() && state != Board::DRAW_MESG {
             rsx! {
-                a { href: "/ttt.php?name={cx.props.name}", "Play Again" }
+                a { href: "/ttt.php?name={url_name}&difficulty={cx.props.difficulty}&variant={variant}", "Play Again" }
             }
         }
     })
 }
 
+#[component]
+fn BadBoard(cx: Scope<BadBoardProps>) -> Element {
+    let error = cx.props.error.to_string();
+    let name = &record::percent_encode(&cx.props.name);
+    cx.render(rsx! {
+        p { "Invalid board: {error}" }
+        a { href: "/ttt.php?name={name}&difficulty={cx.props.difficulty}&variant={cx.props.variant}", "Start over" }
+    })
+}
+
 pub fn get_form_html() -> String {
     let mut app = VirtualDom::new(Home);
     let _ = app.rebuild();
@@ -259,11 +446,332 @@ pub fn get_form_html() -> String {
     )
 }
 
-pub fn accept_from_html(name: String, encoding: String) -> String {
-    let mut app = VirtualDom::new_with_props(Play, PlayProps { name, encoding });
+fn signing_scope(variant: Variant) -> String {
+    format!("ttt:{variant}")
+}
+
+// the move list and, with a signer, the board signature for a board link
+fn board_params(board: &Board, signer: &Option<Signer>) -> String {
+    let mut params = format!("&moves={}", board.history());
+    if let Some(signer) = signer {
+        let sig = signer.sign(&signing_scope(board.grid.variant()), &board.to_string());
+        params.push_str(&format!("&sig={sig}"));
+    }
+    params
+}
+
+// true if `signature` was produced by `signer` for this board
+pub fn verify_board(signer: &Signer, variant: Variant, encoding: &str, signature: &str) -> bool {
+    signer.verify(&signing_scope(variant), encoding, signature)
+}
+
+// every position of a recorded game
+pub fn replay(record: &Record) -> Result<Replay, String> {
+    let variant = variant_from_name(&record.variant).ok_or(format!(
+        "Unknown tic-tac-toe variant \"{}\"",
+        record.variant
+    ))?;
+    let mut board = Board::with_variant(variant);
+    let diagram = |board: &Board| {
+        vec![Diagram {
+            title: "Board",
+            cells: board.grid.symbols(),
+        }]
+    };
+    let mut positions = vec![diagram(&board)];
+    for mv in &record.moves {
+        let cell = record::parse_square(mv).ok_or(format!("Invalid move \"{mv}\""))?;
+        if board.outcome().is_over() {
+            return Err(format!("Move \"{mv}\" comes after the end of the game"));
+        }
+        board
+            .apply_move(cell)
+            .map_err(|err| format!("Move \"{mv}\": {err}"))?;
+        positions.push(diagram(&board));
+    }
+    Ok(Replay {
+        positions,
+        outcome: board.outcome(),
+    })
+}
+
+// an empty encoding starts a new game, board links are signed when a signer
+// is given. `moves` is the move list from the previous page, if any. the
+// game's record comes with the page once the game is over
+pub fn accept_from_html(
+    name: String,
+    encoding: String,
+    moves: &str,
+    difficulty: Difficulty,
+    variant: Variant,
+    signer: Option<Signer>,
+) -> Result<(String, Option<Record>), ParseError> {
+    let mut board = match encoding.as_str() {
+        "" => Board::with_variant(variant),
+        encoding => Board::parse(variant, encoding)?.with_history(moves),
+    };
+    // the server only replies once X has made a move
+    board.reply(difficulty);
+    let finished = board.outcome().is_over().then(|| board.record(&name));
+    let mut app = VirtualDom::new_with_props(
+        Play,
+        PlayProps {
+            name,
+            board,
+            difficulty,
+            signer,
+        },
+    );
+    let _ = app.rebuild();
+    Ok((
+        format!(
+            "<!DOCTYPE html><html lang='en'>{}</html",
+            dioxus_ssr::render(&app)
+        ),
+        finished,
+    ))
+}
+
+pub fn parse_error_html(
+    name: String,
+    difficulty: Difficulty,
+    variant: Variant,
+    error: ParseError,
+) -> String {
+    let mut app = VirtualDom::new_with_props(
+        BadBoard,
+        BadBoardProps {
+            name,
+            difficulty,
+            variant,
+            error,
+        },
+    );
     let _ = app.rebuild();
     format!(
         "<!DOCTYPE html><html lang='en'>{}</html",
         dioxus_ssr::render(&app)
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a 3x3 encoding from its nine cells
+    fn encoding(cells: [&str; 9]) -> String {
+        cells.join(" ")
+    }
+
+    #[test]
+    fn parses_and_prints_the_same_board() {
+        let text = encoding(["X", "O", "", "", "X", "", "", "", "O"]);
+        let board = Board::parse(Variant::TIC_TAC_TOE, &text).unwrap();
+        assert_eq!(board.to_string(), text);
+        assert_eq!(text.parse::<Board>().unwrap(), board);
+        assert_eq!(
+            Board::parse(Variant::TIC_TAC_TOE, &encoding([""; 9])).unwrap(),
+            Board::with_variant(Variant::TIC_TAC_TOE)
+        );
+    }
+
+    #[test]
+    fn wrong_length() {
+        assert_eq!(
+            Board::parse(Variant::TIC_TAC_TOE, "X O"),
+            Err(ParseError::WrongLength {
+                expected: 9,
+                found: 2
+            })
+        );
+        // a doubled separator is one cell too many
+        let text = format!("{} ", encoding(["X", "", "", "", "", "", "", "", ""]));
+        assert_eq!(
+            Board::parse(Variant::TIC_TAC_TOE, &text),
+            Err(ParseError::WrongLength {
+                expected: 9,
+                found: 10
+            })
+        );
+        assert!(matches!(
+            "X O X".parse::<Board>(),
+            Err(ParseError::WrongLength { found: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn bad_symbol() {
+        for symbol in ["x", "Y", "XO", "0"] {
+            let mut cells = [""; 9];
+            cells[4] = symbol;
+            assert_eq!(
+                Board::parse(Variant::TIC_TAC_TOE, &encoding(cells)),
+                Err(ParseError::BadSymbol {
+                    symbol: symbol.to_owned(),
+                    cell: 4,
+                }),
+                "{symbol:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn impossible_counts() {
+        let text = encoding(["X", "X", "", "", "", "", "", "", ""]);
+        assert_eq!(
+            Board::parse(Variant::TIC_TAC_TOE, &text),
+            Err(ParseError::ImpossibleCounts { x: 2, o: 0 })
+        );
+        let text = encoding(["O", "", "", "", "", "", "", "", ""]);
+        assert_eq!(
+            Board::parse(Variant::TIC_TAC_TOE, &text),
+            Err(ParseError::ImpossibleCounts { x: 0, o: 1 })
+        );
+    }
+
+    #[test]
+    fn both_players_win() {
+        let text = encoding(["X", "X", "X", "O", "O", "O", "", "", ""]);
+        assert_eq!(
+            Board::parse(Variant::TIC_TAC_TOE, &text),
+            Err(ParseError::BothPlayersWin)
+        );
+    }
+
+    // every game X can play against the perfect server from `board`, X to
+    // move. returns the games played and the games X won
+    fn explore(board: &Board) -> (usize, usize) {
+        let (mut games, mut x_wins) = (0, 0);
+        for mv in board.legal_moves() {
+            let mut board = board.clone();
+            board.apply_move(mv).unwrap();
+            if !board.outcome().is_over() {
+                board.reply(Difficulty::Perfect).unwrap();
+            }
+            match board.outcome() {
+                Outcome::InProgress => {
+                    let (more_games, more_wins) = explore(&board);
+                    games += more_games;
+                    x_wins += more_wins;
+                }
+                outcome => {
+                    games += 1;
+                    x_wins += usize::from(outcome == Outcome::Win(Player::X));
+                }
+            }
+        }
+        (games, x_wins)
+    }
+
+    #[test]
+    fn perfect_never_loses_on_3x3() {
+        let (games, x_wins) = explore(&Board::with_variant(Variant::TIC_TAC_TOE));
+        assert!(games > 500, "{games}");
+        assert_eq!(x_wins, 0);
+    }
+
+    #[test]
+    fn easy_and_medium_play_legal_moves() {
+        for variant in [
+            Variant::TIC_TAC_TOE,
+            Variant::TIC_TAC_TOE_4X4,
+            Variant::GOMOKU,
+        ] {
+            for difficulty in [Difficulty::Easy, Difficulty::Medium] {
+                let mut board = Board::with_variant(variant);
+                while !board.outcome().is_over() {
+                    let mv = game::random_move(&board).unwrap();
+                    board.apply_move(mv).unwrap();
+                    let legal = board.legal_moves();
+                    match board.reply(difficulty) {
+                        Some(reply) => assert!(legal.contains(&reply), "{reply:?}"),
+                        None => assert!(board.outcome().is_over()),
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn links_encode_the_name() {
+        let name = "\"><script>x</script>".to_owned();
+        let page = parse_error_html(
+            name.clone(),
+            Difficulty::Easy,
+            Variant::TIC_TAC_TOE,
+            ParseError::BothPlayersWin,
+        );
+        assert!(
+            page.contains("name=%22%3E%3Cscript%3Ex%3C%2Fscript%3E&"),
+            "{page}"
+        );
+        assert!(!page.contains("<script"), "{page}");
+
+        let (page, _) = accept_from_html(
+            name,
+            encoding(["X", "", "", "", "O", "", "", "", ""]),
+            "0,0.1,1",
+            Difficulty::Easy,
+            Variant::TIC_TAC_TOE,
+            None,
+        )
+        .unwrap();
+        assert!(!page.contains("<script"), "{page}");
+    }
+
+    // a 3x3 board reached by `moves`, with its history
+    fn played(moves: &[(usize, usize)]) -> Board {
+        Board::replay(Variant::TIC_TAC_TOE, moves).unwrap()
+    }
+
+    // the same board as a client sends it back, without a history
+    fn bare(board: &Board) -> Board {
+        Board::parse(Variant::TIC_TAC_TOE, &board.to_string()).unwrap()
+    }
+
+    #[test]
+    fn a_history_that_replays_is_kept() {
+        let board = played(&[(1, 1), (0, 0), (2, 2)]);
+        assert_eq!(board.history(), "1,1.0,0.2,2");
+        let restored = bare(&board).with_history("1,1.0,0.2,2");
+        assert_eq!(restored, board);
+        assert_eq!(restored.move_count(), 3);
+    }
+
+    #[test]
+    fn a_history_that_does_not_replay_is_dropped() {
+        let board = bare(&played(&[(1, 1), (0, 0), (2, 2)]));
+        for history in [
+            // too short, another board, a repeated cell
+            "1,1.0,0",
+            "0,0.1,1.2,2",
+            "1,1.1,1.2,2",
+            // off the board or not moves at all
+            "1,1.0,0.3,3",
+            "1,1.0,0.2,-2",
+            "1,1.0,0.x,y",
+            "1;1.0;0.2;2",
+            "garbage",
+        ] {
+            let restored = board.clone().with_history(history);
+            assert_eq!(restored, board, "{history:?}");
+            assert_eq!(restored.move_count(), 0);
+            assert_eq!(restored.undo(), None);
+        }
+    }
+
+    #[test]
+    fn undo_takes_back_a_pair() {
+        let board = played(&[(1, 1), (0, 0), (2, 2), (0, 2)]);
+        assert_eq!(board.undo(), Some(played(&[(1, 1), (0, 0)])));
+        assert_eq!(played(&[(1, 1), (0, 0)]).undo(), Some(played(&[])));
+    }
+
+    #[test]
+    fn undo_takes_back_an_unanswered_move() {
+        let board = played(&[(1, 1), (0, 0), (2, 2)]);
+        assert_eq!(board.undo(), Some(played(&[(1, 1), (0, 0)])));
+        assert_eq!(played(&[(1, 1)]).undo(), Some(played(&[])));
+        assert_eq!(played(&[]).undo(), None);
+    }
+}